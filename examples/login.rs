@@ -10,10 +10,9 @@ async fn main() -> anyhow::Result<()> {
     let password = dotenvy::var("MI_PASS")?;
     let token_path = dotenvy::var("MI_TOKEN")?;
 
-    let token_store = TokenStore::new(token_path).await;
-    let mut account_svc = Account::new(username, password, token_store);
-    if account_svc.login(MIIO_SID).await {
-        println!("==");
-    }
+    let token_store = TokenStore::new(token_path).await?;
+    let mut account_svc = Account::new(username, Some(password), token_store);
+    account_svc.login(MIIO_SID).await?;
+    println!("==");
     Ok(())
 }
@@ -11,11 +11,11 @@ async fn main() -> anyhow::Result<()> {
     let password = dotenvy::var("MI_PASS")?;
     let token_path = dotenvy::var("MI_TOKEN")?;
 
-    let token_store = TokenStore::new(token_path).await;
-    let account = Account::new(username, password, token_store);
+    let token_store = TokenStore::new(token_path).await?;
+    let account = Account::new(username, Some(password), token_store);
     let miio_svc = MiIOService::new(account, None);
     miio_svc
         .miot_action("102130584", (5, 1), Some(vec![json!("测试,哈哈哈, 你好")]))
-        .await?;
+        .await??;
     Ok(())
 }
@@ -0,0 +1,198 @@
+use std::{fmt::Display, future::Future, pin::Pin};
+
+use log::{debug, warn};
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use reqwest_cookie_store::CookieStoreMutex;
+use serde_json::Value;
+use url::Url;
+
+use crate::errors::{Error, Result};
+
+use super::Account;
+
+/// 图形验证码错误或需要输入图形验证码
+const CAPTCHA_CODE: i64 = 87001;
+/// 验证码输入错误后的最大重试次数
+const MAX_CAPTCHA_ATTEMPTS: usize = 3;
+
+pub type CaptchaFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+/// 识别登录图形验证码
+///
+/// 闭包 `Fn(&[u8]) -> Result<String, E>` 已实现该trait，错误转换为 [`Error::Captcha`]。
+/// 闭包在 [`tokio::task::spawn_blocking`] 中执行，可以阻塞读取标准输入
+pub trait CaptchaSolver: Send + Sync {
+    /// image 为验证码图片内容，返回识别出的文字
+    fn solve<'a>(&'a self, image: &'a [u8]) -> CaptchaFuture<'a>;
+}
+
+impl<F, E> CaptchaSolver for F
+where
+    F: Fn(&[u8]) -> std::result::Result<String, E> + Clone + Send + Sync + 'static,
+    E: Display,
+{
+    fn solve<'a>(&'a self, image: &'a [u8]) -> CaptchaFuture<'a> {
+        let solver = self.clone();
+        let image = image.to_vec();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || solver(&image).map_err(|e| e.to_string()))
+                .await
+                .map_err(|e| Error::Captcha(format!("solve captcha task failed: {e}")))?
+                .map_err(|e| Error::Captcha(format!("solve captcha failed: {e}")))
+        })
+    }
+}
+
+/// 根据 serviceLoginAuth2 的响应判断是否需要识别验证码，返回验证码图片地址
+///
+/// attempts 为已提交验证码的次数，超过 [`MAX_CAPTCHA_ATTEMPTS`] 时返回错误
+fn captcha_url(resp: &Value, attempts: usize, has_solver: bool) -> Result<Option<&str>> {
+    let captcha_url = resp["captchaUrl"].as_str().unwrap_or_default();
+    if resp["code"] != CAPTCHA_CODE && captcha_url.is_empty() {
+        return Ok(None);
+    }
+    if captcha_url.is_empty() || !has_solver {
+        return Err(Error::CaptchaRequired(if captcha_url.is_empty() {
+            resp.to_string()
+        } else {
+            captcha_url.to_owned()
+        }));
+    }
+    if attempts >= MAX_CAPTCHA_ATTEMPTS {
+        return Err(Error::Captcha(format!(
+            "captcha still required after {attempts} attempts"
+        )));
+    }
+    Ok(Some(captcha_url))
+}
+
+/// getCode 返回的 ick 路径可能为 /pass/getCode，重新写入根路径以便 serviceLoginAuth2 携带
+fn store_ick(store: &CookieStoreMutex, headers: &HeaderMap) {
+    let base = Url::parse("https://account.xiaomi.com").unwrap();
+    let icks = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| cookie::Cookie::parse(v.to_owned()).ok())
+        .filter(|c| c.name() == "ick")
+        .collect::<Vec<_>>();
+    if icks.is_empty() {
+        return;
+    }
+    let mut store = store.lock().unwrap();
+    for ick in icks {
+        let mut ick = cookie::Cookie::new("ick", ick.value().to_owned());
+        ick.set_domain("account.xiaomi.com");
+        ick.set_path("/");
+        if let Err(e) = store.insert_raw(&ick, &base) {
+            warn!("Failed to insert ick cookie to store: {:?}", e);
+        }
+    }
+}
+
+impl Account {
+    /// 密码登录，遇到图形验证码时调用 [`CaptchaSolver`] 并携带 `captCode` 重新提交
+    pub(super) async fn service_login_auth2(&self, mut auth_param: Value) -> Result<Value> {
+        let mut attempts = 0;
+        loop {
+            let resp = self
+                .service_login("serviceLoginAuth2", Some(auth_param.clone()))
+                .await?;
+
+            let Some(captcha_url) = captcha_url(&resp, attempts, self.captcha_solver.is_some())?
+            else {
+                return Ok(resp);
+            };
+            let solver = self.captcha_solver.clone().unwrap();
+            if attempts > 0 {
+                warn!("Captcha rejected, retry {attempts}");
+            }
+            attempts += 1;
+
+            let image = self.fetch_captcha(captcha_url).await?;
+            let code = solver.solve(&image).await?;
+            auth_param["captCode"] = Value::String(code.trim().to_owned());
+        }
+    }
+
+    /// 下载验证码图片，响应中的 `ick` cookie 需在提交时一并带上
+    async fn fetch_captcha(&self, captcha_url: &str) -> Result<Vec<u8>> {
+        let base = Url::parse("https://account.xiaomi.com").unwrap();
+        let url = base
+            .join(captcha_url)
+            .map_err(|e| Error::Captcha(format!("parse url {captcha_url} failed: {e}")))?;
+        debug!("Account::fetch_captcha: url:{url}");
+
+        let response = self
+            .client
+            .get(url.as_str())
+            .send()
+            .await
+            .map_err(|e| Error::transport(url.as_str(), e))?;
+
+        store_ick(&self.store, response.headers());
+
+        let image = response
+            .bytes()
+            .await
+            .map_err(|e| Error::transport(url.as_str(), e))?;
+        Ok(image.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn captcha_retry_limit() {
+        let required = json!({"code": CAPTCHA_CODE, "captchaUrl": "/pass/getCode?icodeType=login"});
+        assert!(matches!(
+            captcha_url(&json!({"code": 0}), 3, true),
+            Ok(None)
+        ));
+        assert!(matches!(
+            captcha_url(&required, 0, false),
+            Err(Error::CaptchaRequired(_))
+        ));
+        assert!(matches!(
+            captcha_url(&json!({"code": CAPTCHA_CODE}), 0, true),
+            Err(Error::CaptchaRequired(_))
+        ));
+        for attempts in 0..MAX_CAPTCHA_ATTEMPTS {
+            assert_eq!(
+                captcha_url(&required, attempts, true).unwrap(),
+                Some("/pass/getCode?icodeType=login")
+            );
+        }
+        assert!(matches!(
+            captcha_url(&required, MAX_CAPTCHA_ATTEMPTS, true),
+            Err(Error::Captcha(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ick_cookie_and_solver() {
+        let store = CookieStoreMutex::default();
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "ick=abc; Path=/pass/getCode".parse().unwrap());
+        store_ick(&store, &headers);
+        let url = Url::parse("https://account.xiaomi.com/pass/serviceLoginAuth2").unwrap();
+        let cookies: Vec<_> = store
+            .lock()
+            .unwrap()
+            .get_request_values(&url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        assert_eq!(cookies, ["ick=abc"]);
+
+        let solver = |image: &[u8]| match image {
+            b"jpg" => Ok("ab12".to_owned()),
+            _ => Err("unreadable"),
+        };
+        assert_eq!(solver.solve(b"jpg").await.unwrap(), "ab12");
+        assert!(matches!(solver.solve(b"png").await, Err(Error::Captcha(_))));
+    }
+}
@@ -0,0 +1,545 @@
+use std::{sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::{debug, error};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, USER_AGENT},
+    Client, StatusCode,
+};
+use reqwest_cookie_store::CookieStoreMutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{
+    errors::Error,
+    resp::Response,
+    store::{Token, TokenStore},
+};
+
+pub use captcha::{CaptchaFuture, CaptchaSolver};
+pub use qr::QrLogin;
+pub use verify::{LoginChallenge, VerifyChannel, VerifyCodeProvider, VerifyFuture};
+
+mod captcha;
+mod qr;
+mod refresh;
+mod verify;
+
+// Account主结构体
+#[derive(Clone)]
+pub struct Account {
+    store: Arc<CookieStoreMutex>,
+    client: Client,
+    username: String,
+    password: Option<String>,
+    token_store: TokenStore,
+    verify_provider: Option<Arc<dyn VerifyCodeProvider>>,
+    captcha_solver: Option<Arc<dyn CaptchaSolver>>,
+    challenge: Option<LoginChallenge>,
+    /// serviceToken 未带过期时间时假定的有效期
+    service_token_ttl: Duration,
+    /// 距过期不足该时间时提前刷新
+    refresh_ahead: Duration,
+    pub token: Token,
+}
+
+impl Account {
+    /// password 为空时只能通过已保存的 passToken 或扫码登录
+    pub fn new(username: String, password: Option<String>, token_store: TokenStore) -> Self {
+        let token = token_store.token.clone();
+        let store = token.cookies.clone();
+        let client = reqwest::Client::builder()
+            .cookie_provider(store.clone())
+            .build()
+            .expect("Build http client failed");
+
+        Self {
+            store,
+            client,
+            username,
+            password,
+            token_store,
+            verify_provider: None,
+            captcha_solver: None,
+            challenge: None,
+            service_token_ttl: refresh::DEFAULT_SERVICE_TOKEN_TTL,
+            refresh_ahead: refresh::DEFAULT_REFRESH_AHEAD,
+            token,
+        }
+    }
+
+    /// 无密码模式，只通过 token 中保存的 passToken、userId、deviceId 恢复登录态
+    ///
+    /// passToken 失效时返回 [`Error::ReauthRequired`]，需在别处用密码或扫码重新登录。
+    pub fn from_pass_token(token_store: TokenStore) -> Self {
+        let username = token_store.token.user_id.clone();
+        Self::new(username, None, token_store)
+    }
+
+    /// 未保存密码，无法自动用密码重新登录
+    pub fn is_passwordless(&self) -> bool {
+        self.password.is_none()
+    }
+
+    /// 是否保存了 passToken
+    pub fn has_pass_token(&self) -> bool {
+        !self.token.pass_token.is_empty() && !self.token.user_id.is_empty()
+    }
+
+    /// 设置安全验证码提供者，登录遇到短信/邮件验证时调用
+    pub fn set_verify_provider(&mut self, provider: Arc<dyn VerifyCodeProvider>) {
+        self.verify_provider = Some(provider);
+    }
+
+    /// 设置图形验证码识别器，密码登录被判定为风险时调用
+    pub fn set_captcha_solver(&mut self, solver: Arc<dyn CaptchaSolver>) {
+        self.captcha_solver = Some(solver);
+    }
+
+    /// 最近一次登录中未完成的安全验证
+    pub fn challenge(&self) -> Option<&LoginChallenge> {
+        self.challenge.as_ref()
+    }
+
+    // 登录方法
+    pub async fn login(&mut self, sid: &str) -> Result<(), Error> {
+        debug!("Account::login sid: {}", sid);
+
+        let mut resp = self
+            .service_login(&format!("serviceLogin?sid={}&_json=true", sid), None)
+            .await?;
+        if resp["code"] != 0 {
+            let Some(password) = &self.password else {
+                return Err(Error::ReauthRequired(if self.has_pass_token() {
+                    format!("passToken expired or revoked, login {sid} with password or QR code")
+                } else {
+                    format!("no passToken saved, login {sid} with password or QR code")
+                }));
+            };
+            let auth_param = json!({
+                "_json": "true",
+                "sid": resp["sid"],
+                "qs": resp["qs"],
+                "_sign": resp["_sign"],
+                "callback": resp["callback"],
+                "user": self.username,
+                "hash": format!("{:X}", md5::compute(password.as_bytes())),
+            });
+            resp = self.service_login_auth2(auth_param).await?;
+        }
+
+        // 需要短信/邮件安全验证
+        if let Some(url) = resp["notificationUrl"]
+            .as_str()
+            .filter(|u| !u.is_empty())
+            .map(str::to_owned)
+        {
+            resp = self.handle_challenge(sid, &url).await?;
+        }
+
+        if resp["code"] != 0 || resp["location"].as_str().unwrap_or_default().is_empty() {
+            return Err(Error::Api {
+                code: resp["code"].as_i64().unwrap_or(-1) as i32,
+                message: resp["desc"]
+                    .as_str()
+                    .or(resp["description"].as_str())
+                    .unwrap_or("login failed")
+                    .to_owned(),
+            });
+        }
+
+        self.finish_login(sid, &resp).await
+    }
+
+    /// 处理登录成功: 记录 passToken/userId/ssecurity，获取 serviceToken 并保存
+    pub(super) async fn finish_login(&mut self, sid: &str, resp: &Value) -> Result<(), Error> {
+        let expires_at = self.service_token(resp).await?;
+        self.save_login(sid, resp, expires_at).await
+    }
+
+    /// 使用登录响应获取 serviceToken，返回其过期时间，不修改账号状态
+    pub(super) async fn service_token(&self, resp: &Value) -> Result<u64, Error> {
+        let nonce = resp["nonce"].to_string();
+        let ssecurity = resp["ssecurity"].as_str().unwrap_or_default();
+        let location = resp["location"].as_str().unwrap_or_default();
+        // 获取安全令牌
+        Ok(self
+            .security_token_service(location, &nonce, ssecurity)
+            .await?
+            .unwrap_or_else(|| refresh::now() + self.service_token_ttl.as_secs()))
+    }
+
+    /// 记录登录结果并保存 token
+    pub(super) async fn save_login(
+        &mut self,
+        sid: &str,
+        resp: &Value,
+        expires_at: u64,
+    ) -> Result<(), Error> {
+        self.record_login(resp);
+        let ssecurity = resp["ssecurity"].as_str().unwrap_or_default();
+        self.token.sid.insert(sid.to_owned(), ssecurity.to_owned());
+        self.token.sid_expires.insert(sid.to_owned(), expires_at);
+        self.token_store.token = self.token.clone();
+        self.token_store.save().await
+    }
+
+    /// 记录登录响应中的 userId 与 passToken
+    ///
+    /// 使用 passToken 免密登录时响应可能不再返回 passToken，此时保留原值。
+    fn record_login(&mut self, resp: &Value) {
+        match &resp["userId"] {
+            Value::String(user_id) if !user_id.is_empty() => self.token.user_id = user_id.clone(),
+            Value::Number(user_id) => self.token.user_id = user_id.to_string(),
+            _ => {}
+        }
+        if let Some(pass_token) = resp["passToken"].as_str().filter(|t| !t.is_empty()) {
+            self.token.pass_token = pass_token.to_owned();
+        }
+    }
+
+    /// 按 token 设置 passToken、userId、deviceId cookie，serviceLogin 据此免密登录
+    fn apply_pass_token(&self) {
+        let mut store = self.store.lock().unwrap();
+        let request_url = Url::parse("https://account.xiaomi.com").unwrap();
+        let mut cookies = vec![("deviceId", &self.token.device_id)];
+        if self.has_pass_token() {
+            cookies.push(("userId", &self.token.user_id));
+            cookies.push(("passToken", &self.token.pass_token));
+        }
+        for (name, value) in cookies {
+            let cookie = cookie::Cookie::build((name, value.as_str()))
+                .domain("account.xiaomi.com")
+                .path("/")
+                .build();
+            if let Err(e) = store.insert_raw(&cookie, &request_url) {
+                error!("Failed to insert cookie {name}: {e:?}");
+            }
+        }
+    }
+
+    // 服务登录请求
+    async fn service_login(&self, uri: &str, data: Option<Value>) -> Result<Value, Error> {
+        let url = format!("https://account.xiaomi.com/pass/{}", uri);
+        debug!("Account::service_login: url:{url} data: {data:?}");
+        self.apply_pass_token();
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            "APP/com.xiaomi.mihome APPV/6.0.103 iosPassportSDK/3.9.0 iOS/14.4 miHSTS"
+                .parse()
+                .unwrap(),
+        );
+
+        let mut builder = self
+            .client
+            .request(
+                if data.is_some() {
+                    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+                    reqwest::Method::POST
+                } else {
+                    reqwest::Method::GET
+                },
+                &url,
+            )
+            .headers(headers);
+
+        if let Some(data) = data {
+            builder = builder.form(&data);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| Error::transport(&url, e))?;
+        let status_code = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| Error::transport(&url, e))?;
+        if status_code != StatusCode::OK {
+            return Err(Error::Http {
+                url,
+                status: status_code,
+                body: text,
+            });
+        }
+        let json_str = text.trim_start_matches("&&&START&&&");
+
+        serde_json::from_str(json_str).map_err(|e| Error::deserialize(json_str, e))
+    }
+
+    // 安全令牌服务，返回 serviceToken cookie 的过期时间
+    async fn security_token_service(
+        &self,
+        location: &str,
+        nonce: &str,
+        ssecurity: &str,
+    ) -> Result<Option<u64>, Error> {
+        debug!("Account::security_token_service ");
+        let nsec = format!("nonce={}&{}", nonce, ssecurity);
+        let mut hasher = Sha1::new();
+        hasher.update(nsec.as_bytes());
+        let client_sign = STANDARD.encode(hasher.finalize());
+
+        let url = format!(
+            "{location}&clientSign={}",
+            urlencoding::encode(&client_sign)
+        );
+
+        let u = Url::parse(&url)
+            .map_err(|e| Error::SecurityTokenService(format!("parse url {url} failed: {e}")))?;
+
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| Error::transport(&url, e))?;
+
+        let status_code = response.status();
+        if status_code != StatusCode::OK {
+            let text = response
+                .text()
+                .await
+                .map_err(|e| Error::transport(&url, e))?;
+            return Err(Error::Http {
+                url,
+                status: status_code,
+                body: text,
+            });
+        }
+
+        let expires_at = refresh::service_token_expiry(response.headers());
+        if u.domain() == Some("sts.api.io.mi.com") {
+            self.recreate_cookie_for_domain();
+        }
+        Ok(expires_at)
+    }
+
+    // Mi请求方法
+    pub async fn request<R, P>(
+        &mut self,
+        sid: &str,
+        url: &str,
+        data: Option<P>,
+        headers: Option<HeaderMap>,
+        relogin: Option<bool>,
+    ) -> Result<Response<R>, Error>
+    where
+        R: for<'de> Deserialize<'de>,
+        P: Serialize + Clone,
+    {
+        debug!(
+            "account::request {headers:?} url:{url} data:{:?}",
+            serde_json::to_string(&data)
+        );
+
+        self.ensure_sid(sid).await?;
+        match send(&self.client, url, data.as_ref(), headers.as_ref()).await? {
+            Reply::Done(resp) => Ok(resp),
+            Reply::AuthFailed(_) if relogin.unwrap_or(true) => {
+                // 只重试一次，避免登录态持续无效时无限递归
+                self.invalidate(sid).await?;
+                Box::pin(self.request(sid, url, data, headers, Some(false))).await
+            }
+            Reply::AuthFailed(message) => {
+                Err(Error::AuthExpired(format!("request url {url}: {message}")))
+            }
+        }
+    }
+
+    /// 与 [`Account::request`] 相同，但只在登录和处理失效时持有锁，共享账号的并发请求不会互相等待
+    pub async fn request_shared<R, P>(
+        account: &Mutex<Account>,
+        sid: &str,
+        url: &str,
+        data: Option<P>,
+        headers: Option<HeaderMap>,
+        relogin: Option<bool>,
+    ) -> Result<Response<R>, Error>
+    where
+        R: for<'de> Deserialize<'de>,
+        P: Serialize + Clone,
+    {
+        debug!("account::request_shared url:{url}");
+        let client = {
+            let mut account = account.lock().await;
+            account.ensure_sid(sid).await?;
+            account.client.clone()
+        };
+        match send(&client, url, data.as_ref(), headers.as_ref()).await? {
+            Reply::Done(resp) => Ok(resp),
+            Reply::AuthFailed(_) if relogin.unwrap_or(true) => {
+                account.lock().await.invalidate(sid).await?;
+                Box::pin(Self::request_shared(
+                    account,
+                    sid,
+                    url,
+                    data,
+                    headers,
+                    Some(false),
+                ))
+                .await
+            }
+            Reply::AuthFailed(message) => {
+                Err(Error::AuthExpired(format!("request url {url}: {message}")))
+            }
+        }
+    }
+
+    pub async fn get_sid(&mut self, sid: &str) -> Result<String, Error> {
+        self.ensure_sid(sid).await?;
+        self.token
+            .sid
+            .get(sid)
+            .cloned()
+            .ok_or_else(|| Error::AuthExpired(format!("no ssecurity for sid {sid}")))
+    }
+
+    /// 重新为指定域名创建cookie
+    /// 因为MiIOtoken获取的是sts.api.io.mi.com域名下的，而实际请求是api.io.mi.com域名
+    /// 不能自动将cookie转移到api.io.mi.com域名下，所以需要手动创建
+    fn recreate_cookie_for_domain(&self) {
+        let mut store = self.store.lock().unwrap();
+        let api_url = Url::parse("https://api.io.mi.com").unwrap();
+        let raw_cookies = store
+            .iter_any()
+            .filter_map(|c| {
+                if c.domain() == Some("sts.api.io.mi.com") {
+                    let cookie_str = c.to_string();
+                    let mut raw_cookie = cookie::Cookie::parse(cookie_str).unwrap();
+                    raw_cookie.set_domain("api.io.mi.com");
+                    Some(raw_cookie)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        for cookie in raw_cookies {
+            if let Err(e) = store.insert_raw(&cookie, &api_url) {
+                error!("Failed to insert raw cookie {cookie:?} to store: {:?}", e);
+            }
+        }
+    }
+}
+
+/// 单次请求的结果，登录态失效时由调用方决定是否重新登录
+enum Reply<R> {
+    Done(Response<R>),
+    AuthFailed(String),
+}
+
+/// 发送请求，不读写账号状态，cookie 由 client 共享的 cookie store 提供
+async fn send<R, P>(
+    client: &Client,
+    url: &str,
+    data: Option<&P>,
+    headers: Option<&HeaderMap>,
+) -> Result<Reply<R>, Error>
+where
+    R: for<'de> Deserialize<'de>,
+    P: Serialize,
+{
+    let method = if data.is_some() {
+        reqwest::Method::POST
+    } else {
+        reqwest::Method::GET
+    };
+
+    let mut builder = client.request(method, url);
+
+    if let Some(headers) = headers {
+        builder = builder.headers(headers.clone());
+    }
+
+    if let Some(data) = data {
+        builder = builder.form(data);
+    }
+
+    let response = builder.send().await.map_err(|e| Error::transport(url, e))?;
+
+    let status = response.status();
+    let text = response
+        .text()
+        .await
+        .map_err(|e| Error::transport(url, e))?;
+    match status {
+        StatusCode::OK => {}
+        StatusCode::UNAUTHORIZED => return Ok(Reply::AuthFailed(text)),
+        _ => {
+            return Err(Error::Http {
+                url: url.to_owned(),
+                status,
+                body: text,
+            })
+        }
+    }
+
+    let resp: Response<R> =
+        serde_json::from_str(&text).map_err(|e| Error::deserialize(text.clone(), e))?;
+    match resp.code {
+        0 => Ok(Reply::Done(resp)),
+        _ if resp.message.to_lowercase().contains("auth") => Ok(Reply::AuthFailed(resp.message)),
+        code => Err(Error::Api {
+            code,
+            message: resp.message,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryBackend;
+
+    #[tokio::test]
+    async fn pass_token_cookies() {
+        let backend = MemoryBackend::with_content(
+            r#"{"device_id":"D","user_id":"123","pass_token":"V1:pt","sid":{},"cookies":[]}"#,
+        );
+        let store = TokenStore::with_backend(Arc::new(backend), None)
+            .await
+            .unwrap();
+        let account = Account::from_pass_token(store);
+        assert!(account.is_passwordless());
+        assert!(account.has_pass_token());
+
+        account.apply_pass_token();
+        let cookies = account.token.cookie_infos();
+        let value = |name: &str| {
+            cookies
+                .iter()
+                .find(|c| c.domain == "account.xiaomi.com" && c.name == name)
+                .map(|c| c.value.as_str())
+        };
+        assert_eq!(value("userId"), Some("123"));
+        assert_eq!(value("passToken"), Some("V1:pt"));
+        assert_eq!(value("deviceId"), Some("D"));
+    }
+
+    #[tokio::test]
+    async fn keep_pass_token() {
+        let backend = MemoryBackend::with_content(
+            r#"{"device_id":"D","user_id":"123","pass_token":"V1:pt","sid":{},"cookies":[]}"#,
+        );
+        let store = TokenStore::with_backend(Arc::new(backend), None)
+            .await
+            .unwrap();
+        let mut account = Account::from_pass_token(store);
+
+        account.record_login(&json!({"userId": 123, "passToken": ""}));
+        assert_eq!(account.token.pass_token, "V1:pt");
+        account.record_login(&json!({"code": 0}));
+        assert_eq!(account.token.pass_token, "V1:pt");
+        assert_eq!(account.token.user_id, "123");
+
+        account.record_login(&json!({"userId": "456", "passToken": "V1:new"}));
+        assert_eq!(account.token.pass_token, "V1:new");
+        assert_eq!(account.token.user_id, "456");
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::debug;
+use serde_json::Value;
+use url::Url;
+
+use crate::errors::Error;
+
+use super::Account;
+
+/// 单次长轮询的超时时间，超时后继续轮询直到二维码过期
+const POLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 扫码登录信息
+#[derive(Debug, Clone)]
+pub struct QrLogin {
+    pub sid: String,
+    /// 二维码内容，使用米家 App 扫描
+    pub login_url: String,
+    /// 二维码图片地址
+    pub qr_image_url: String,
+    /// 二维码有效期
+    pub timeout: Duration,
+    lp: String,
+}
+
+impl Account {
+    /// 获取扫码登录二维码，之后调用 [`Account::wait_qr_login`] 等待扫码
+    pub async fn login_qr(&self, sid: &str) -> Result<QrLogin, Error> {
+        debug!("Account::login_qr sid: {}", sid);
+        let resp = self
+            .service_login(&format!("serviceLogin?sid={}&_json=true", sid), None)
+            .await?;
+
+        let field = |key: &str| resp[key].as_str().unwrap_or_default().to_owned();
+        let dc = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            .to_string();
+        let url = Url::parse_with_params(
+            "https://account.xiaomi.com/longPolling/loginUrl",
+            &[
+                ("_qrsize", "240".to_owned()),
+                ("qs", field("qs")),
+                ("bizDeviceType", String::new()),
+                ("callback", field("callback")),
+                ("_json", "true".to_owned()),
+                ("theme", String::new()),
+                ("sid", sid.to_owned()),
+                ("needTheme", "false".to_owned()),
+                ("showActiveX", "false".to_owned()),
+                ("serviceParam", field("serviceParam")),
+                ("_local", "zh_CN".to_owned()),
+                ("_sign", field("_sign")),
+                ("_dc", dc),
+            ],
+        )
+        .unwrap();
+
+        let resp = self.long_polling(url.as_str(), None).await?;
+        if resp["code"] != 0 {
+            return Err(Error::ServiceLogin(format!(
+                "get login qr code failed: {resp}"
+            )));
+        }
+
+        Ok(QrLogin {
+            sid: sid.to_owned(),
+            login_url: resp["loginUrl"].as_str().unwrap_or_default().to_owned(),
+            qr_image_url: resp["qr"].as_str().unwrap_or_default().to_owned(),
+            timeout: Duration::from_secs(resp["timeout"].as_u64().unwrap_or(300)),
+            lp: resp["lp"].as_str().unwrap_or_default().to_owned(),
+        })
+    }
+
+    /// 长轮询等待扫码确认，成功后与密码登录一样保存 passToken、userId 与 ssecurity
+    pub async fn wait_qr_login(&mut self, qr: &QrLogin) -> Result<(), Error> {
+        debug!("Account::wait_qr_login sid: {}", qr.sid);
+        let deadline = Instant::now() + qr.timeout;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(Error::ServiceLogin("login qr code expired".to_owned()));
+            }
+            match self.long_polling(&qr.lp, Some(POLL_TIMEOUT)).await {
+                Ok(resp) if resp["code"] == 0 && resp["ssecurity"].is_string() => {
+                    return self.finish_login(&qr.sid, &resp).await;
+                }
+                Ok(resp) => debug!("Account::wait_qr_login pending: {resp}"),
+                Err(e) => debug!("Account::wait_qr_login pending: {e}"),
+            }
+            // 避免服务端立即返回时频繁请求
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn long_polling(&self, url: &str, timeout: Option<Duration>) -> Result<Value, Error> {
+        let mut builder = self.client.get(url);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        let text = builder
+            .send()
+            .await
+            .map_err(|e| Error::transport(url, e))?
+            .text()
+            .await
+            .map_err(|e| Error::transport(url, e))?;
+        let json_str = text.trim_start_matches("&&&START&&&");
+        serde_json::from_str(json_str).map_err(|e| Error::deserialize(json_str, e))
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use serde_json::Value;
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::errors::Error;
+
+use super::Account;
+
+/// serviceToken cookie 通常不带过期时间，此时假定的有效期
+pub(super) const DEFAULT_SERVICE_TOKEN_TTL: Duration = Duration::from_secs(24 * 3600);
+/// 默认提前1小时刷新
+pub(super) const DEFAULT_REFRESH_AHEAD: Duration = Duration::from_secs(3600);
+
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// 从 STS 响应的 Set-Cookie 中读取 serviceToken 的过期时间
+pub(super) fn service_token_expiry(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| cookie::Cookie::parse(value.to_str().ok()?.to_owned()).ok())
+        .filter(|cookie| cookie.name() == "serviceToken")
+        .find_map(
+            |cookie| match (cookie.max_age(), cookie.expires_datetime()) {
+                (Some(max_age), _) => Some(now().saturating_add_signed(max_age.whole_seconds())),
+                (None, Some(at)) => u64::try_from(at.unix_timestamp()).ok(),
+                (None, None) => None,
+            },
+        )
+}
+
+impl Account {
+    /// serviceToken 未带过期时间时假定的有效期，默认24小时
+    pub fn set_service_token_ttl(&mut self, ttl: Duration) {
+        self.service_token_ttl = ttl;
+    }
+
+    /// 距过期不足 ahead 时在下次请求前刷新，默认1小时
+    pub fn set_refresh_ahead(&mut self, ahead: Duration) {
+        self.refresh_ahead = ahead;
+    }
+
+    /// sid 的 serviceToken 过期时间，unix 秒，旧版 token 文件中没有记录
+    pub fn service_token_expiry(&self, sid: &str) -> Option<u64> {
+        self.token.sid_expires.get(sid).copied()
+    }
+
+    /// sid 已登录且即将过期
+    pub fn needs_refresh(&self, sid: &str) -> bool {
+        match self.service_token_expiry(sid) {
+            Some(at) if self.token.sid.contains_key(sid) => {
+                at <= now() + self.refresh_ahead.as_secs()
+            }
+            _ => false,
+        }
+    }
+
+    /// 使用 passToken 重新获取 sid 的 serviceToken，不使用密码
+    ///
+    /// passToken 也已失效时返回 [`Error::ReauthRequired`]。
+    pub async fn refresh(&mut self, sid: &str) -> Result<(), Error> {
+        debug!("Account::refresh sid: {}", sid);
+        let (resp, expires_at) = self.fetch_refresh(sid).await?;
+        self.save_login(sid, &resp, expires_at).await
+    }
+
+    /// 刷新的网络请求部分，返回登录响应与 serviceToken 过期时间，不修改账号状态
+    async fn fetch_refresh(&self, sid: &str) -> Result<(Value, u64), Error> {
+        let resp = self
+            .service_login(&format!("serviceLogin?sid={}&_json=true", sid), None)
+            .await?;
+        if resp["code"] != 0 || resp["location"].as_str().unwrap_or_default().is_empty() {
+            return Err(Error::ReauthRequired(format!(
+                "refresh {sid} with passToken failed, login with password or QR code"
+            )));
+        }
+        let expires_at = self.service_token(&resp).await?;
+        Ok((resp, expires_at))
+    }
+
+    /// 只清除 sid 的登录态，其他 sid 与 passToken 保留
+    pub async fn invalidate(&mut self, sid: &str) -> Result<(), Error> {
+        debug!("Account::invalidate sid: {}", sid);
+        self.token.sid.remove(sid);
+        self.token.sid_expires.remove(sid);
+        self.token_store.token = self.token.clone();
+        self.token_store.save().await
+    }
+
+    /// 确保 sid 已登录，即将过期时提前刷新
+    pub(super) async fn ensure_sid(&mut self, sid: &str) -> Result<(), Error> {
+        if !self.token.sid.contains_key(sid) {
+            return self.login(sid).await;
+        }
+        if !self.needs_refresh(sid) {
+            return Ok(());
+        }
+        match self.refresh(sid).await {
+            Ok(()) => Ok(()),
+            // 尚未过期时继续使用旧的 serviceToken
+            Err(e) if self.service_token_expiry(sid).is_some_and(|at| at > now()) => {
+                warn!("Refresh {sid} ahead of expiry failed: {e}");
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Refresh {sid} failed: {e}, login again");
+                self.invalidate(sid).await?;
+                self.login(sid).await
+            }
+        }
+    }
+
+    /// 启动后台任务，每隔 interval 检查并刷新即将过期的 sid，适合长期运行的服务
+    ///
+    /// 丢弃返回的 [`JoinHandle`] 不会停止任务，需要时调用 `abort`。
+    /// 网络请求期间不持有账号锁，只在写回结果时短暂加锁。
+    pub fn spawn_refresh_task(account: Arc<Mutex<Account>>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let (snapshot, sids) = {
+                    let account = account.lock().await;
+                    let mut sids: Vec<_> = account.token.sid.keys().cloned().collect();
+                    sids.retain(|sid| account.needs_refresh(sid));
+                    (account.clone(), sids)
+                };
+                for sid in sids {
+                    let result = match snapshot.fetch_refresh(&sid).await {
+                        Ok((resp, expires_at)) => {
+                            account
+                                .lock()
+                                .await
+                                .save_login(&sid, &resp, expires_at)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(()) => info!("Refreshed service token of {sid}"),
+                        Err(e) => warn!("Refresh service token of {sid} failed: {e}"),
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_token_expiry_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "userId=1; Path=/".parse().unwrap());
+        assert_eq!(service_token_expiry(&headers), None);
+
+        headers.append(
+            SET_COOKIE,
+            "serviceToken=abc; Expires=Wed, 01 Jan 2031 00:00:00 GMT; Path=/"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(service_token_expiry(&headers), Some(1924992000));
+
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "serviceToken=abc; Max-Age=600".parse().unwrap());
+        let at = service_token_expiry(&headers).unwrap();
+        assert!(at.abs_diff(now() + 600) <= 1);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::errors::Error;
+
+use super::Account;
+
+/// 登录时小米要求的安全验证(短信或邮件验证码)
+#[derive(Debug, Clone)]
+pub struct LoginChallenge {
+    pub sid: String,
+    /// serviceLoginAuth2 返回的 `notificationUrl`，也可在浏览器中打开完成验证
+    pub verify_url: String,
+    pub context: String,
+    /// 可选的验证方式
+    pub channels: Vec<VerifyChannel>,
+}
+
+impl LoginChallenge {
+    /// 从 notificationUrl 中读取 context，可选的验证方式需另外查询
+    fn new(sid: &str, notification_url: &str) -> Result<Self, Error> {
+        let context = Url::parse(notification_url)
+            .ok()
+            .and_then(|u| {
+                u.query_pairs()
+                    .find(|(k, _)| k == "context")
+                    .map(|(_, v)| v.into_owned())
+            })
+            .ok_or_else(|| {
+                Error::Verification(format!("no context in notificationUrl {notification_url}"))
+            })?;
+        Ok(Self {
+            sid: sid.to_owned(),
+            verify_url: notification_url.to_owned(),
+            context,
+            channels: Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyChannel {
+    Phone,
+    Email,
+}
+
+impl VerifyChannel {
+    fn from_flag(flag: i64) -> Option<Self> {
+        match flag {
+            4 => Some(Self::Phone),
+            8 => Some(Self::Email),
+            _ => None,
+        }
+    }
+
+    /// identity/list 响应中的 options，没有时取 flag
+    fn from_options(resp: &Value) -> Vec<Self> {
+        let mut channels: Vec<_> = resp["options"]
+            .as_array()
+            .map(|options| {
+                options
+                    .iter()
+                    .filter_map(|o| o.as_i64().and_then(Self::from_flag))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if channels.is_empty() {
+            channels.extend(resp["flag"].as_i64().and_then(Self::from_flag));
+        }
+        channels
+    }
+
+    fn flag(&self) -> i32 {
+        match self {
+            Self::Phone => 4,
+            Self::Email => 8,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Phone => "Phone",
+            Self::Email => "Email",
+        }
+    }
+}
+
+pub type VerifyFuture<'a> = Pin<Box<dyn Future<Output = crate::Result<String>> + Send + 'a>>;
+
+/// 提供安全验证码
+///
+/// 闭包 `Fn(&LoginChallenge, VerifyChannel) -> Result<String, E>` 已实现该trait，错误转换为 [`Error::Verification`]。
+/// 闭包在 [`tokio::task::spawn_blocking`] 中执行，可以阻塞读取标准输入
+pub trait VerifyCodeProvider: Send + Sync {
+    /// 选择验证方式，默认使用第一个
+    fn select_channel(&self, challenge: &LoginChallenge) -> Option<VerifyChannel> {
+        challenge.channels.first().copied()
+    }
+
+    /// 验证码已发送到 channel，返回用户收到的验证码
+    fn verify_code<'a>(
+        &'a self,
+        challenge: &'a LoginChallenge,
+        channel: VerifyChannel,
+    ) -> VerifyFuture<'a>;
+}
+
+impl<F, E> VerifyCodeProvider for F
+where
+    F: Fn(&LoginChallenge, VerifyChannel) -> Result<String, E> + Clone + Send + Sync + 'static,
+    E: Display,
+{
+    fn verify_code<'a>(
+        &'a self,
+        challenge: &'a LoginChallenge,
+        channel: VerifyChannel,
+    ) -> VerifyFuture<'a> {
+        let provider = self.clone();
+        let challenge = challenge.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                provider(&challenge, channel).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| Error::Verification(format!("verify code task failed: {e}")))?
+            .map_err(|e| Error::Verification(format!("get verify code failed: {e}")))
+        })
+    }
+}
+
+impl Account {
+    /// 获取验证方式，有验证码提供者时完成验证，否则记录待处理的验证
+    pub(super) async fn handle_challenge(
+        &mut self,
+        sid: &str,
+        notification_url: &str,
+    ) -> Result<Value, Error> {
+        let challenge = self.login_challenge(sid, notification_url).await?;
+        match self.verify_provider.clone() {
+            Some(provider) => {
+                let resp = self.verify(&challenge, provider.as_ref()).await?;
+                self.challenge = None;
+                Ok(resp)
+            }
+            None => {
+                self.challenge = Some(challenge.clone());
+                Err(Error::VerificationRequired(Box::new(challenge)))
+            }
+        }
+    }
+
+    /// 根据 notificationUrl 获取可用的验证方式
+    pub(super) async fn login_challenge(
+        &self,
+        sid: &str,
+        notification_url: &str,
+    ) -> Result<LoginChallenge, Error> {
+        debug!("Account::login_challenge");
+        let mut challenge = LoginChallenge::new(sid, notification_url)?;
+        let resp = self
+            .identity_request(
+                "list",
+                &challenge,
+                &[("supportedMask", "0".to_owned())],
+                None,
+            )
+            .await?;
+        challenge.channels = VerifyChannel::from_options(&resp);
+        Ok(challenge)
+    }
+
+    /// 发送验证码、提交用户输入并完成 ticket 交换，成功后重新 serviceLogin 获取 ssecurity
+    pub(super) async fn verify(
+        &self,
+        challenge: &LoginChallenge,
+        provider: &dyn VerifyCodeProvider,
+    ) -> Result<Value, Error> {
+        debug!("Account::verify");
+        let channel = provider.select_channel(challenge).ok_or_else(|| {
+            Error::Verification(format!(
+                "no supported verify channel, verify in browser: {}",
+                challenge.verify_url
+            ))
+        })?;
+
+        self.identity_request(
+            &format!("auth/send{}Ticket", channel.name()),
+            challenge,
+            &[],
+            Some(json!({"retry": 0, "icode": "", "_json": "true"})),
+        )
+        .await?;
+
+        let code = provider.verify_code(challenge, channel).await?;
+
+        let resp = self
+            .identity_request(
+                &format!("auth/verify{}", channel.name()),
+                challenge,
+                &[("_flag", channel.flag().to_string())],
+                Some(json!({"ticket": code.trim(), "trust": "true", "_json": "true"})),
+            )
+            .await?;
+        let location = resp["location"].as_str().unwrap_or_default();
+        if location.is_empty() {
+            return Err(Error::Verification(format!("verify code rejected: {resp}")));
+        }
+
+        // 跟随跳转写入 passToken 等 cookie
+        self.client
+            .get(location)
+            .send()
+            .await
+            .map_err(|e| Error::transport(location, e))?;
+
+        let resp = self
+            .service_login(
+                &format!("serviceLogin?sid={}&_json=true", challenge.sid),
+                None,
+            )
+            .await?;
+        if resp["code"] != 0 {
+            return Err(Error::Verification(format!(
+                "serviceLogin after verify failed: {resp}"
+            )));
+        }
+        Ok(resp)
+    }
+
+    async fn identity_request(
+        &self,
+        path: &str,
+        challenge: &LoginChallenge,
+        query: &[(&str, String)],
+        data: Option<Value>,
+    ) -> Result<Value, Error> {
+        let mut url = Url::parse(&format!("https://account.xiaomi.com/identity/{path}")).unwrap();
+        {
+            let mut pairs = url.query_pairs_mut();
+            let dc = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            pairs.append_pair("_dc", &dc.to_string());
+            pairs.append_pair("sid", &challenge.sid);
+            pairs.append_pair("context", &challenge.context);
+            for (k, v) in query {
+                pairs.append_pair(k, v);
+            }
+        }
+        debug!("Account::identity_request: url:{url} data: {data:?}");
+
+        let builder = match &data {
+            Some(data) => self.client.post(url.as_str()).form(data),
+            None => self.client.get(url.as_str()),
+        };
+        let text = builder
+            .send()
+            .await
+            .map_err(|e| Error::transport(url.as_str(), e))?
+            .text()
+            .await
+            .map_err(|e| Error::transport(url.as_str(), e))?;
+        let json_str = text.trim_start_matches("&&&START&&&");
+        let resp: Value =
+            serde_json::from_str(json_str).map_err(|e| Error::deserialize(json_str, e))?;
+        if resp["code"] != 0 {
+            return Err(Error::Verification(format!(
+                "request url {url} failed: {resp}"
+            )));
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn challenge_round_trip() {
+        let url = "https://account.xiaomi.com/identity/authStart?sid=micoapi&context=abc%3D";
+        let mut challenge = LoginChallenge::new("micoapi", url).unwrap();
+        assert_eq!(challenge.context, "abc=");
+        assert!(LoginChallenge::new("micoapi", "https://account.xiaomi.com/").is_err());
+
+        challenge.channels = VerifyChannel::from_options(&json!({"options": [8, 4, 2]}));
+        assert_eq!(
+            challenge.channels,
+            [VerifyChannel::Email, VerifyChannel::Phone]
+        );
+        assert_eq!(
+            VerifyChannel::from_options(&json!({"options": [], "flag": 4})),
+            [VerifyChannel::Phone]
+        );
+        assert!(VerifyChannel::from_options(&json!({"flag": 16})).is_empty());
+        assert_eq!(VerifyChannel::Email.flag(), 8);
+
+        // 同步闭包在阻塞线程中执行，收到的是同一个验证
+        let provider = |c: &LoginChallenge, channel: VerifyChannel| {
+            if c.context == "abc=" && channel == VerifyChannel::Email {
+                Ok("123456".to_owned())
+            } else {
+                Err("unexpected challenge")
+            }
+        };
+        let channel = provider.select_channel(&challenge).unwrap();
+        let code = provider.verify_code(&challenge, channel).await.unwrap();
+        assert_eq!(code, "123456");
+        let err = provider
+            .verify_code(&challenge, VerifyChannel::Phone)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Verification(e) if e.contains("unexpected challenge")));
+    }
+}
@@ -1,6 +1,11 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::bail;
 use clap::{CommandFactory, Parser};
 use command::Commands;
-use mi_service::{init_tracing_subscriber, Account, MiIOService, TokenStore};
+use mi_service::{
+    init_tracing_subscriber, AccountManager, Profile, ProfileConfig, DEFAULT_PROFILE,
+};
 use tracing::Level;
 
 mod command;
@@ -16,16 +21,32 @@ struct Cli {
         default_value = "info"
     )]
     log_level: Option<Level>,
-    #[arg(short, long, help = "Username账号", env = "MI_USER")]
-    user: String,
-    #[arg(short, long, help = "Password密码", env = "MI_PASS")]
-    pass: String,
+    #[arg(
+        long,
+        help = "使用配置文件中的账号，未指定 --user 时默认为配置中的 default",
+        env = "MI_PROFILE"
+    )]
+    profile: Option<String>,
+    #[arg(
+        long,
+        help = "配置文件路径，默认 ~/.config/mi-service/config.toml",
+        env = "MI_CONFIG"
+    )]
+    config: Option<PathBuf>,
     #[arg(
         short,
         long,
-        help = "Token文件路径",
-        env = "MI_TOKEN",
-        default_value = "~/.mi.token"
+        help = "Username账号，指定时忽略配置文件",
+        env = "MI_USER"
+    )]
+    user: Option<String>,
+    #[arg(short, long, help = "Password密码，为空时需扫码登录", env = "MI_PASS")]
+    pass: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Token文件路径，默认 ~/.mi.token",
+        env = "MI_TOKEN"
     )]
     token_file: Option<String>,
     #[command(subcommand)]
@@ -37,17 +58,34 @@ async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
     let cli = Cli::parse();
     init_tracing_subscriber(cli.log_level);
+    let Some(command) = cli.command else {
+        Cli::command().print_help()?;
+        return Ok(());
+    };
 
-    let token_store = TokenStore::new(cli.token_file.clone().unwrap().to_owned()).await;
-    let account = Account::new(cli.user, cli.pass, token_store);
+    // 命令行指定账号时不读取配置文件
+    let config = match (&cli.user, &cli.profile) {
+        (Some(user), None) => ProfileConfig::single(
+            DEFAULT_PROFILE,
+            Profile {
+                user: user.clone(),
+                password: cli.pass.clone(),
+                token_file: cli.token_file.clone(),
+                ..Default::default()
+            },
+        ),
+        _ => match &cli.config {
+            Some(path) => ProfileConfig::load(path).await?,
+            None => ProfileConfig::load_default().await?,
+        },
+    };
+    if config.profiles.is_empty() {
+        bail!("no account specified, set MI_USER or add a profile to the config file");
+    }
 
-    let miio_svc = MiIOService::new(account, None);
+    let mut manager = AccountManager::new(config);
+    manager.set_verify_provider(Arc::new(command::prompt::verify_code));
+    manager.set_captcha_solver(Arc::new(command::prompt::solve_captcha));
 
-    match cli.command {
-        Some(cmd) => cmd.exec(miio_svc).await?,
-        None => {
-            Cli::command().print_help().unwrap();
-        }
-    }
-    Ok(())
+    command.exec(&manager, cli.profile.as_deref()).await
 }
@@ -1,13 +1,169 @@
+use anyhow::{anyhow, bail};
 use clap::Parser;
+use mi_service::MiIOService;
+use serde::Serialize;
+use serde_json::{json, Value};
 
+use super::target::{parse_iid, parse_value, Target};
+
+/// action 子命令
 #[derive(Debug, Parser)]
 pub struct Args {
-    pub did: String,
+    #[arg(help = "设备did或名称")]
+    device: String,
+    #[arg(help = "动作，siid.aiid")]
+    action: String,
+    #[arg(help = "动作参数，按规格中 in 的顺序")]
+    args: Vec<String>,
+    #[arg(long, help = "以JSON格式输出")]
+    json: bool,
+}
+
+/// 动作的单个输出参数
+#[derive(Debug, Serialize)]
+struct OutValue {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    piid: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    value: Value,
 }
 
 impl Args {
-    pub async fn exec(&self) -> anyhow::Result<()> {
-        println!("spec: {:?}", self);
+    pub async fn exec(&self, svc: MiIOService) -> anyhow::Result<()> {
+        let (siid, aiid) =
+            parse_iid(&self.action).ok_or_else(|| anyhow!("invalid action {}", self.action))?;
+        let target = Target::resolve(&svc, &self.device).await?;
+        let spec = target.spec(&svc).await;
+
+        let action = match &spec {
+            Some(spec) => Some(
+                spec.action(siid, aiid)
+                    .ok_or_else(|| anyhow!("action {siid}.{aiid} not found in spec"))?,
+            ),
+            None => None,
+        };
+        let property = |piid: i32| spec.as_ref().and_then(|s| s.property(siid, piid));
+
+        let args = match action {
+            Some(action) => {
+                if action.r#in.len() != self.args.len() {
+                    bail!(
+                        "action {} expects {} arguments {:?}, got {}",
+                        action.description,
+                        action.r#in.len(),
+                        action.r#in,
+                        self.args.len()
+                    );
+                }
+                action
+                    .r#in
+                    .iter()
+                    .zip(&self.args)
+                    .map(|(piid, text)| parse_value(property(*piid), text))
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            }
+            None => self
+                .args
+                .iter()
+                .map(|text| parse_value(None, text))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        };
+
+        let out = svc
+            .miot_action(&target.did, (siid, aiid), Some(args))
+            .await?
+            .map_err(|status| anyhow!("action {siid}.{aiid} failed: {status}"))?;
+
+        let out_piids = action.map(|a| a.out.as_slice()).unwrap_or_default();
+        let out = out_values(&out, out_piids, |piid| {
+            property(piid).map(|p| p.description.clone())
+        });
+
+        if self.json {
+            let result = json!({
+                "action": format!("{siid}.{aiid}"),
+                "description": action.map(|a| a.description.clone()),
+                "out": out,
+            });
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            return Ok(());
+        }
+
+        match action {
+            Some(action) => println!("{siid}.{aiid} {}: ok", action.description),
+            None => println!("{siid}.{aiid}: ok"),
+        }
+        if !out.is_empty() {
+            println!("{:<8}{:<32}VALUE", "PIID", "DESCRIPTION");
+            for item in &out {
+                println!(
+                    "{:<8}{:<32}{}",
+                    item.piid.map(|p| p.to_string()).unwrap_or("-".to_owned()),
+                    item.description.as_deref().unwrap_or("-"),
+                    item.value
+                );
+            }
+        }
         Ok(())
     }
 }
+
+/// out 可能为值数组，也可能为 {piid, value} 对象数组，值数组按规格中 out 的顺序对应属性
+fn out_values(
+    out: &Value,
+    out_piids: &[i32],
+    description: impl Fn(i32) -> Option<String>,
+) -> Vec<OutValue> {
+    out.as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, it)| {
+            let piid = it["piid"]
+                .as_i64()
+                .map(|p| p as i32)
+                .or_else(|| out_piids.get(i).copied());
+            let value = if it.is_object() && it.get("value").is_some() {
+                it["value"].clone()
+            } else {
+                it.clone()
+            };
+            OutValue {
+                piid,
+                description: piid.and_then(&description),
+                value,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_shapes() {
+        let description = |piid: i32| (piid == 3).then(|| "Temperature".to_owned());
+
+        let out = out_values(&json!([25.5, "ok"]), &[3, 4], description);
+        assert_eq!(out.len(), 2);
+        assert_eq!((out[0].piid, out[0].value.clone()), (Some(3), json!(25.5)));
+        assert_eq!(out[0].description.as_deref(), Some("Temperature"));
+        assert_eq!((out[1].piid, out[1].value.clone()), (Some(4), json!("ok")));
+
+        let out = out_values(
+            &json!([{"piid": 4, "value": "ok"}, {"piid": 3, "value": 25.5}]),
+            &[3, 4],
+            description,
+        );
+        assert_eq!((out[0].piid, out[0].value.clone()), (Some(4), json!("ok")));
+        assert_eq!((out[1].piid, out[1].value.clone()), (Some(3), json!(25.5)));
+        assert_eq!(out[1].description.as_deref(), Some("Temperature"));
+
+        // 没有规格时只有值
+        let out = out_values(&json!([1]), &[], |_| None);
+        assert_eq!((out[0].piid, out[0].value.clone()), (None, json!(1)));
+        assert!(out_values(&json!([]), &[], |_| None).is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use clap::Parser;
+use mi_service::{discover, join_cloud, MiIOService};
+
+/// discover 子命令
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[arg(short, long, help = "等待设备响应的时间(秒)", default_value = "3")]
+    timeout: u64,
+    #[arg(long, help = "不合并云端设备列表")]
+    no_cloud: bool,
+    #[arg(long, help = "以JSON格式输出")]
+    json: bool,
+}
+
+impl Args {
+    pub async fn exec(&self, svc: MiIOService) -> anyhow::Result<()> {
+        let mut found = discover(Duration::from_secs(self.timeout)).await?;
+        if !self.no_cloud {
+            let devices = svc.devices(None, None, None).await?;
+            join_cloud(&mut found, &devices);
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&found)?);
+            return Ok(());
+        }
+
+        println!(
+            "{:<12}{:<17}{:<32}{:<34}NAME",
+            "DID", "IP", "MODEL", "TOKEN"
+        );
+        for device in &found {
+            let mut name = device.name.clone().unwrap_or_default();
+            if matches!(&device.cloud_ip, Some(ip) if *ip != device.ip.to_string()) {
+                name.push_str(&format!(
+                    " (cloud ip {})",
+                    device.cloud_ip.as_deref().unwrap_or_default()
+                ));
+            }
+            println!(
+                "{:<12}{:<17}{:<32}{:<34}{}",
+                device.did,
+                device.ip,
+                device.model.as_deref().unwrap_or("-"),
+                device.token.as_deref().unwrap_or("-"),
+                name
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use clap::Parser;
+use mi_service::{Account, MIIO_SID};
+use qrcode::{render::unicode, QrCode};
+use tokio::sync::Mutex;
+
+/// login 子命令
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[arg(long, help = "扫码登录，无需密码")]
+    qr: bool,
+    #[arg(long, help = "服务ID，如 xiaomiio、micoapi", default_value = MIIO_SID)]
+    sid: String,
+}
+
+impl Args {
+    pub async fn exec(&self, account: Arc<Mutex<Account>>) -> anyhow::Result<()> {
+        let mut account = account.lock().await;
+        if !self.qr {
+            account.login(&self.sid).await?;
+            println!("login {} success", self.sid);
+            return Ok(());
+        }
+
+        let qr = account.login_qr(&self.sid).await?;
+        let code = QrCode::new(qr.login_url.as_bytes())?;
+        let image = code
+            .render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build();
+        println!("{image}");
+        println!("Scan with Mi Home app, or open {}", qr.qr_image_url);
+
+        account.wait_qr_login(&qr).await?;
+        println!("login {} success", self.sid);
+        Ok(())
+    }
+}
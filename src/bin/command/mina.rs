@@ -0,0 +1,337 @@
+use std::{pin::pin, time::Duration};
+
+use anyhow::{anyhow, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use log::warn;
+use mi_service::{
+    Conversation, DeviceSelector, MiNADevice, MiNaService, PlayOperation, SendOutcome,
+};
+use serde_json::json;
+
+/// mina 子命令，小爱音箱相关操作
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[command(subcommand)]
+    command: MinaCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum MinaCommand {
+    #[command(about = "List XiaoAi speakers")]
+    List {
+        #[arg(long, help = "以JSON格式输出")]
+        json: bool,
+    },
+    #[command(about = "Speak text on a speaker")]
+    Tts {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(help = "播报的文字")]
+        text: String,
+    },
+    #[command(about = "Set speaker volume")]
+    Volume {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(help = "音量(0-100)", value_parser = clap::value_parser!(i32).range(0..=100))]
+        volume: i32,
+    },
+    #[command(about = "Execute a text command as if spoken to XiaoAi")]
+    Exec {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(help = "文字指令，如 \"打开客厅灯\"")]
+        text: String,
+        #[arg(long, help = "静默执行，不播报回复")]
+        silent: bool,
+    },
+    #[command(about = "Control the media player")]
+    Player {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        op: Operation,
+    },
+    #[command(about = "Show player status")]
+    Status {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(long, help = "以JSON格式输出")]
+        json: bool,
+    },
+    #[command(about = "Play an audio url")]
+    PlayUrl {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        url: String,
+    },
+    #[command(about = "Set player loop type")]
+    Loop {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(help = "循环模式，与 status 中的 loop_type 一致")]
+        loop_type: i32,
+    },
+    #[command(about = "Pause playing after seconds")]
+    Timer {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(help = "秒数，为空时取消定时")]
+        seconds: Option<u32>,
+    },
+    #[command(about = "Show recent conversations")]
+    History {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(short = 'n', long, help = "获取条数", default_value = "10")]
+        limit: usize,
+        #[arg(long, help = "以JSON格式输出")]
+        json: bool,
+    },
+    #[command(about = "Watch new conversations")]
+    Watch {
+        #[arg(help = "音箱 deviceID、名称或序号(从1开始)")]
+        device: String,
+        #[arg(long, help = "轮询间隔(秒)", default_value = "2")]
+        interval: u64,
+        #[arg(long, help = "以JSON格式逐行输出")]
+        json: bool,
+    },
+    #[command(about = "Set volume and/or speak text, like Yonsm/MiService")]
+    Send {
+        #[arg(
+            long,
+            alias = "devno",
+            help = "音箱: all(-1)、序号(从1开始)、id:deviceID、alias:别名或名称关键字",
+            default_value = "all",
+            allow_hyphen_values = true
+        )]
+        to: DeviceSelector,
+        #[arg(long, help = "以JSON格式输出每个音箱的结果")]
+        json: bool,
+        #[arg(long, help = "音量(0-100)", value_parser = clap::value_parser!(i32).range(0..=100))]
+        volume: Option<i32>,
+        #[arg(help = "播报的文字")]
+        message: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, ValueEnum)]
+enum Operation {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Prev,
+}
+
+impl From<&Operation> for PlayOperation {
+    fn from(op: &Operation) -> Self {
+        match op {
+            Operation::Play => Self::Play,
+            Operation::Pause => Self::Pause,
+            Operation::Toggle => Self::Toggle,
+            Operation::Stop => Self::Stop,
+            Operation::Next => Self::Next,
+            Operation::Prev => Self::Prev,
+        }
+    }
+}
+
+impl Args {
+    pub async fn exec(&self, svc: MiNaService) -> anyhow::Result<()> {
+        match &self.command {
+            MinaCommand::List { json } => {
+                let devices = svc.devices(None).await?.data;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&devices)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:<4}{:<40}{:<12}{:<10}NAME",
+                    "NO", "DEVICE ID", "HARDWARE", "PRESENCE"
+                );
+                for (i, device) in devices.iter().enumerate() {
+                    let mut name = device.name.clone();
+                    if !device.alias.is_empty() && device.alias != device.name {
+                        name.push_str(&format!(" ({})", device.alias));
+                    }
+                    println!(
+                        "{:<4}{:<40}{:<12}{:<10}{}",
+                        i + 1,
+                        device.id,
+                        device.hardware,
+                        device.presence,
+                        name
+                    );
+                }
+            }
+            MinaCommand::Tts { device, text } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.text_to_speech(&device.id, text).await? {
+                    bail!("text to speech on {} failed", device.name);
+                }
+            }
+            MinaCommand::Volume { device, volume } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.player_set_volume(&device.id, *volume).await? {
+                    bail!("set volume on {} failed", device.name);
+                }
+            }
+            MinaCommand::Exec {
+                device,
+                text,
+                silent,
+            } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.execute_command(&device, text, *silent).await? {
+                    bail!("execute command on {} failed", device.name);
+                }
+            }
+            MinaCommand::Player { device, op } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.player_play_operation(&device.id, op.into()).await? {
+                    bail!("{op:?} on {} failed", device.name);
+                }
+            }
+            MinaCommand::Status { device, json } => {
+                let device = resolve(&svc, device).await?;
+                let status = svc.player_get_status(&device.id).await?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&status)?);
+                    return Ok(());
+                }
+                println!("status: {:?}", status.status);
+                println!("volume: {}", status.volume);
+                println!("loop:   {}", status.loop_type);
+                if let Some(track) = &status.track {
+                    let title = match (&track.title, &track.artist) {
+                        (Some(title), Some(artist)) => format!("{title} - {artist}"),
+                        (Some(title), None) => title.clone(),
+                        _ => track.audio_id.clone(),
+                    };
+                    println!(
+                        "track:  {title} ({}s/{}s)",
+                        track.position / 1000,
+                        track.duration / 1000
+                    );
+                }
+            }
+            MinaCommand::PlayUrl { device, url } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.player_play_url(&device.id, url).await? {
+                    bail!("play url on {} failed", device.name);
+                }
+            }
+            MinaCommand::Loop { device, loop_type } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.player_set_loop(&device.id, *loop_type).await? {
+                    bail!("set loop on {} failed", device.name);
+                }
+            }
+            MinaCommand::Timer { device, seconds } => {
+                let device = resolve(&svc, device).await?;
+                if !svc.player_set_shutdown_timer(&device.id, *seconds).await? {
+                    bail!("set shutdown timer on {} failed", device.name);
+                }
+            }
+            MinaCommand::History {
+                device,
+                limit,
+                json,
+            } => {
+                let device = resolve(&svc, device).await?;
+                let records = svc.conversations(&device, *limit).await?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&records)?);
+                    return Ok(());
+                }
+                for record in records.iter().rev() {
+                    print_conversation(record);
+                }
+            }
+            MinaCommand::Watch {
+                device,
+                interval,
+                json,
+            } => {
+                let device = resolve(&svc, device).await?;
+                let stream = svc.watch_conversations(&device, Duration::from_secs(*interval));
+                let mut stream = pin!(stream);
+                while let Some(record) = stream.next().await {
+                    match record {
+                        Ok(record) if *json => println!("{}", serde_json::to_string(&record)?),
+                        Ok(record) => print_conversation(&record),
+                        Err(e) => warn!("Poll conversations failed: {e}"),
+                    }
+                }
+            }
+            MinaCommand::Send {
+                to,
+                json,
+                volume,
+                message,
+            } => {
+                if message.is_none() && volume.is_none() {
+                    bail!("nothing to send, specify a message or --volume");
+                }
+                let devices = svc.devices(None).await?.data;
+                let reports = svc
+                    .send_message(&devices, to, message.as_deref(), *volume)
+                    .await?;
+
+                let mut failed = 0;
+                let mut results = Vec::new();
+                for report in &reports {
+                    let (status, detail) = match &report.outcome {
+                        SendOutcome::Sent => ("sent", String::new()),
+                        SendOutcome::Skipped(reason) => ("skipped", reason.clone()),
+                        SendOutcome::Failed(e) => {
+                            failed += 1;
+                            ("failed", e.to_string())
+                        }
+                    };
+                    if *json {
+                        results.push(json!({
+                            "device_id": report.device_id,
+                            "name": report.name,
+                            "status": status,
+                            "detail": detail,
+                        }));
+                    } else {
+                        println!("{:<24}{:<10}{}", report.name, status, detail);
+                    }
+                }
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&results)?);
+                }
+                if failed > 0 {
+                    bail!("send to {failed} of {} speakers failed", reports.len());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_conversation(record: &Conversation) {
+    println!("[{}] {}", record.time / 1000, record.query);
+    if let Some(answer) = record.answer_tts() {
+        println!("    -> {answer}");
+    }
+}
+
+/// 按 deviceID、名称、别名或序号查找音箱
+async fn resolve(svc: &MiNaService, target: &str) -> anyhow::Result<MiNADevice> {
+    let devices = svc.devices(None).await?.data;
+    if let Ok(no) = target.parse::<usize>() {
+        if (1..=devices.len()).contains(&no) {
+            return Ok(devices[no - 1].clone());
+        }
+    }
+    devices
+        .into_iter()
+        .find(|d| d.id == target || d.name == target || d.alias == target)
+        .ok_or_else(|| anyhow!("speaker {target} not found"))
+}
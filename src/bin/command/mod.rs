@@ -1,14 +1,23 @@
 use anyhow::Result;
 use clap::Subcommand;
-use mi_service::MiIOService;
+use mi_service::AccountManager;
 
 mod action;
+mod discover;
 mod list;
+mod login;
+mod mina;
+pub mod prompt;
 mod prop;
+mod shorthand;
 mod spec;
+mod target;
+mod token;
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    #[command(about = "Login and save token, supports QR code login")]
+    Login(login::Args),
     #[command(about = "List all devices")]
     List(list::Args),
     #[command(about = "Get/Set device properties")]
@@ -17,49 +26,36 @@ pub enum Commands {
     Action(action::Args),
     #[command(about = "Get device spec")]
     Spec(spec::Args),
+    #[command(about = "XiaoAi speaker commands")]
+    Mina(mina::Args),
+    #[command(about = "Manage the saved token, e.g. encrypt it at rest")]
+    Token(token::Args),
+    #[command(about = "Discover miIO devices in LAN")]
+    Discover(discover::Args),
     #[command(external_subcommand)]
     External(Vec<String>),
 }
 
 impl Commands {
-    pub async fn exec(&self, svc: MiIOService) -> Result<()> {
+    /// 按需创建服务，同一 profile 的 miio 与 mina 共享账号，两个 sid 的 token 保存在同一文件中
+    pub async fn exec(&self, manager: &AccountManager, profile: Option<&str>) -> Result<()> {
         match self {
-            Commands::List(args) => args.exec(svc).await?,
-            Commands::Prop(args) => args.exec().await?,
-            Commands::Action(args) => args.exec().await?,
-            Commands::Spec(args) => args.exec(svc).await?,
-            Commands::External(_args) => {
-                //     if args.is_empty() {
-                //         Cli::command().print_help().unwrap();
-                //         return Ok(());
-                //     }
-
-                //     match std::env::var("MI_DID").ok() {
-                //         Some(did) => {
-                //             let cmd = &args[0];
-                //             let args = &args[1..];
-                //             let argc = args.len();
-                //             debug!("did: {did} cmd: {cmd:?} args: {args:?}");
-                //             let mut props: Vec<String> = Vec::new();
-                //             let mut step = true;
-                //             let miot = true;
-                //             for item in cmd.split(',') {
-                //                 let (key, value) = item.split_once('=').unwrap_or((item, ""));
-                //                 let (siid, iid) = key.split_once('.').unwrap_or((key, "1"));
-                //                 let mut prop;
-                //                 if siid.chars().all(char::is_numeric)
-                //                     && iid.chars().all(char::is_numeric)
-                //                 {
-                //                     prop =
-                //                         vec![siid.parse::<i32>().unwrap(), iid.parse::<i32>().unwrap()];
-                //                 } else {
-                //                     step = false;
-                //                     break;
-                //                 }
-                //             }
-                //         }
-                //         None => Cli::command().print_help().unwrap(),
-                //     }
+            Commands::Login(args) => args.exec(manager.account(profile).await?).await?,
+            Commands::Token(args) => args.exec(manager, profile).await?,
+            Commands::List(args) => args.exec(manager.miio(profile).await?).await?,
+            Commands::Prop(args) => args.exec(manager.miio(profile).await?).await?,
+            Commands::Action(args) => args.exec(manager.miio(profile).await?).await?,
+            Commands::Spec(args) => args.exec(manager.miio(profile).await?).await?,
+            Commands::Mina(args) => args.exec(manager.mina(profile).await?).await?,
+            Commands::Discover(args) => args.exec(manager.miio(profile).await?).await?,
+            Commands::External(args) => {
+                // profile 中配置的默认设备
+                let config = manager.config();
+                let device = config
+                    .profile(config.resolve_name(profile))?
+                    .device
+                    .as_deref();
+                shorthand::exec(manager.miio(profile).await?, args, device).await?
             }
         }
         Ok(())
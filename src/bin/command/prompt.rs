@@ -0,0 +1,56 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::anyhow;
+use mi_service::{LoginChallenge, VerifyChannel};
+
+/// 从标准输入读取一行，去除首尾空白
+pub fn read_line(prompt: &str) -> anyhow::Result<String> {
+    non_empty(read_input(prompt)?.trim())
+}
+
+/// 读取口令，只去除换行符，首尾空格属于口令
+pub fn read_passphrase(prompt: &str) -> anyhow::Result<String> {
+    non_empty(read_input(prompt)?.trim_end_matches(['\r', '\n']))
+}
+
+fn read_input(prompt: &str) -> anyhow::Result<String> {
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+
+fn non_empty(line: &str) -> anyhow::Result<String> {
+    if line.is_empty() {
+        return Err(anyhow!("no input"));
+    }
+    Ok(line.to_owned())
+}
+
+/// 命令行安全验证: 提示验证码已发送，从标准输入读取
+pub fn verify_code(challenge: &LoginChallenge, channel: VerifyChannel) -> anyhow::Result<String> {
+    eprintln!(
+        "Security verification required for {}, code sent by {channel:?}",
+        challenge.sid
+    );
+    eprintln!("You can also verify in browser: {}", challenge.verify_url);
+    read_line("Verification code: ")
+}
+
+/// 命令行图形验证码: 图片保存为随机名称的临时文件，从标准输入读取识别结果后删除
+pub fn solve_captcha(image: &[u8]) -> anyhow::Result<String> {
+    let mut file = tempfile::Builder::new()
+        .prefix("mi-service-captcha-")
+        .suffix(".jpg")
+        .tempfile()?;
+    file.write_all(image)?;
+    file.flush()?;
+    eprintln!(
+        "Captcha required, open {} to view it",
+        file.path().display()
+    );
+    let code = read_line("Captcha: ");
+    file.close()?;
+    code
+}
@@ -1,8 +1,24 @@
+use anyhow::bail;
 use clap::{Parser, ValueEnum};
+use mi_service::{MiIOService, MiotResult};
+use serde::Serialize;
+use serde_json::Value;
 
+use super::target::{parse_iid, parse_value, Target};
+
+/// prop 子命令
 #[derive(Debug, Parser)]
 pub struct Args {
     op: Operation,
+    #[arg(help = "设备did或名称")]
+    device: String,
+    #[arg(
+        required = true,
+        help = "属性，get: 2.1 或 power，set: 2.1=true 或 power=on"
+    )]
+    props: Vec<String>,
+    #[arg(long, help = "以JSON格式输出")]
+    json: bool,
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -11,16 +27,201 @@ enum Operation {
     Set,
 }
 
+/// 单个属性的读写结果
+#[derive(Debug, Serialize)]
+struct PropResult {
+    prop: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl PropResult {
+    fn new(prop: &str, result: Result<Value, String>) -> Self {
+        let (value, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(e) => (None, Some(e)),
+        };
+        Self {
+            prop: prop.to_owned(),
+            description: None,
+            value,
+            error,
+        }
+    }
+}
+
 impl Args {
-    pub async fn exec(&self) -> anyhow::Result<()> {
+    pub async fn exec(&self, svc: MiIOService) -> anyhow::Result<()> {
+        let target = Target::resolve(&svc, &self.device).await?;
+
+        // miot 属性为 (序号, siid, piid, 值)，旧版 miIO 属性为 (序号, 名称, 值)
+        let mut miot = Vec::new();
+        let mut legacy = Vec::new();
+        for (i, item) in self.props.iter().enumerate() {
+            let (key, value) = match (&self.op, item.split_once('=')) {
+                (Operation::Get, None) => (item.as_str(), ""),
+                (Operation::Set, Some(kv)) => kv,
+                (Operation::Get, Some(_)) => bail!("unexpected value in {item}"),
+                (Operation::Set, None) => bail!("missing value in {item}, expect key=value"),
+            };
+            match parse_iid(key) {
+                Some((siid, piid)) => miot.push((i, siid, piid, value)),
+                None => legacy.push((i, key, value)),
+            }
+        }
+
+        let spec = if miot.is_empty() {
+            None
+        } else {
+            target.spec(&svc).await
+        };
+        let property = |siid: i32, piid: i32| spec.as_ref().and_then(|s| s.property(siid, piid));
+
+        let mut results: Vec<Option<PropResult>> = self.props.iter().map(|_| None).collect();
         match self.op {
             Operation::Get => {
-                println!("get");
+                if !miot.is_empty() {
+                    let iids = miot.iter().map(|(_, s, p, _)| (*s, *p)).collect();
+                    let values = svc.miot_get_props(&target.did, iids).await?;
+                    fill_miot(&mut results, &miot, values);
+                }
+                if !legacy.is_empty() {
+                    let names = legacy.iter().map(|(_, name, _)| name.to_string()).collect();
+                    let values = svc.home_get_props(&target.did, names).await?;
+                    let Some(values) = values.as_array() else {
+                        bail!("unexpected get_prop result: {values}");
+                    };
+                    let props = legacy.iter().map(|(i, name, _)| (*i, name.to_string()));
+                    fill(&mut results, props, values.iter().cloned().map(Ok));
+                }
             }
             Operation::Set => {
-                println!("set");
+                if !miot.is_empty() {
+                    let mut props = Vec::new();
+                    for (_, siid, piid, text) in &miot {
+                        let value = parse_value(property(*siid, *piid), text)?;
+                        props.push((*siid, *piid, value));
+                    }
+                    let values = svc.miot_set_props(&target.did, props).await?;
+                    fill_miot(&mut results, &miot, values);
+                }
+                if !legacy.is_empty() {
+                    let props = legacy
+                        .iter()
+                        .map(|(_, name, text)| Ok((name.to_string(), parse_value(None, text)?)))
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    let codes = svc.home_set_props(&target.did, props.clone()).await?;
+                    let values =
+                        props
+                            .into_iter()
+                            .zip(codes)
+                            .map(|((_, value), code)| match code {
+                                0 => Ok(value),
+                                code => Err(format!("code {code}")),
+                            });
+                    let props = legacy.iter().map(|(i, name, _)| (*i, name.to_string()));
+                    fill(&mut results, props, values);
+                }
             }
         }
+
+        let mut results: Vec<PropResult> = results.into_iter().flatten().collect();
+        for result in results.iter_mut() {
+            if let Some((siid, piid)) = parse_iid(&result.prop) {
+                result.description = property(siid, piid).map(|p| p.description.clone());
+            }
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        } else {
+            println!("{:<12}{:<32}VALUE", "PROP", "DESCRIPTION");
+            for result in &results {
+                let value = match (&result.value, &result.error) {
+                    (_, Some(e)) => format!("error: {e}"),
+                    (Some(v), None) => v.to_string(),
+                    (None, None) => String::new(),
+                };
+                println!(
+                    "{:<12}{:<32}{}",
+                    result.prop,
+                    result.description.as_deref().unwrap_or("-"),
+                    value
+                );
+            }
+        }
+
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        if failed > 0 {
+            bail!("{failed} of {} properties failed", results.len());
+        }
         Ok(())
     }
 }
+
+/// 按请求顺序填入 miot 属性的结果
+fn fill_miot(
+    results: &mut [Option<PropResult>],
+    miot: &[(usize, i32, i32, &str)],
+    values: Vec<MiotResult>,
+) {
+    let props = miot
+        .iter()
+        .map(|(i, siid, piid, _)| (*i, format!("{siid}.{piid}")));
+    fill(
+        results,
+        props,
+        values.into_iter().map(|v| v.map_err(|e| e.to_string())),
+    );
+}
+
+/// 按请求顺序填入结果，响应中缺少的项记为错误
+fn fill(
+    results: &mut [Option<PropResult>],
+    props: impl Iterator<Item = (usize, String)>,
+    mut values: impl Iterator<Item = Result<Value, String>>,
+) {
+    for (i, prop) in props {
+        let value = values
+            .next()
+            .unwrap_or_else(|| Err("missing in response".to_owned()));
+        results[i] = Some(PropResult::new(&prop, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mi_service::MiotStatus;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn fill_missing_items() {
+        let mut results: Vec<Option<PropResult>> = (0..4).map(|_| None).collect();
+        // 请求了3个 miot 属性，响应只返回2项
+        let miot = [(0, 2, 1, ""), (2, 2, 2, ""), (3, 3, 1, "")];
+        let values = vec![Ok(json!(true)), Err(MiotStatus::new(-704042011))];
+        fill_miot(&mut results, &miot, values);
+        // 旧版属性的响应为空数组
+        let legacy = json!([]);
+        let values = legacy.as_array().unwrap().iter().cloned().map(Ok);
+        fill(&mut results, [(1, "power".to_owned())].into_iter(), values);
+
+        let results: Vec<PropResult> = results.into_iter().flatten().collect();
+        let props: Vec<_> = results.iter().map(|r| r.prop.as_str()).collect();
+        assert_eq!(props, ["2.1", "power", "2.2", "3.1"]);
+        assert_eq!(results[0].value, Some(json!(true)));
+        assert_eq!(results[1].error.as_deref(), Some("missing in response"));
+        assert!(results[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("device offline"));
+        assert_eq!(results[3].error.as_deref(), Some("missing in response"));
+    }
+}
@@ -0,0 +1,429 @@
+//! 兼容 Yonsm/MiService 的简写命令，默认设备取自 `MI_DID`，其次为 profile 中的 device
+//!
+//! ```text
+//! 2.1,2.2              读取属性，siid 与 piid 也可用 - 分隔，省略 piid 时为 1
+//! 2.1=#true,2.2=on     设置属性，# 开头按 JSON 解析，否则按规格转换，无规格时与 prop set 相同
+//! 5-1 "text" #3        执行动作，仅有参数 #NA 时表示无参数
+//! power,bright         读取旧版 miIO 属性
+//! power=on             设置旧版 miIO 属性
+//! set_power on         调用旧版 home RPC 方法
+//! prop/get <json>      调用 MIoT 接口
+//! /home/device_list <json>  调用任意 miio 接口
+//! ```
+
+use anyhow::anyhow;
+use mi_service::{MiIOService, MiotResult, MiotSpecProperty};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use super::target::{parse_value, Target};
+
+pub const USAGE: &str = "\
+Get props:   <siid[.piid]>[,...]              2.1,2.2 or power,bright
+Set props:   <siid[.piid]=[#]value>[,...]     2.1=#true,2.2=on or power=on
+Do action:   <siid.aiid> <arg|#NA> [...]      5.1 hello #3
+Home RPC:    <method> <arg> [...]             set_power on
+Call MIoT:   prop/get|prop/set <json>
+Call MiIO:   /<uri> [json]                    /home/device_list '{\"getVirtualModel\":false}'";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseError {
+    #[error("empty command")]
+    Empty,
+    #[error("empty item in {0}")]
+    EmptyItem(String),
+    #[error("empty key in {0}")]
+    EmptyKey(String),
+    #[error("cannot mix miot (siid.piid) and legacy property names in {0}")]
+    MixedKeys(String),
+    #[error("cannot mix get and set in {0}")]
+    MixedGetSet(String),
+    #[error("{0} takes a single key without value when called with arguments")]
+    InvalidCall(String),
+    #[error("invalid value {0}: {1}")]
+    InvalidValue(String, String),
+}
+
+/// 命令中的值，`#` 开头的按 JSON 解析，否则保留文本，执行时按 [`parse_value`] 转换
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Text(String),
+    Value(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropKey {
+    Miot(i32, i32),
+    Legacy(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shorthand {
+    GetProps(Vec<PropKey>),
+    SetProps(Vec<(PropKey, Arg)>),
+    Action {
+        siid: i32,
+        aiid: i32,
+        args: Vec<Arg>,
+    },
+    Home {
+        method: String,
+        args: Vec<Arg>,
+    },
+    Miot {
+        cmd: String,
+        params: Value,
+    },
+    Miio {
+        uri: String,
+        data: Value,
+    },
+}
+
+/// 解析简写命令，args[0] 为命令，其余为参数
+pub fn parse(args: &[String]) -> Result<Shorthand, ParseError> {
+    let (cmd, argv) = args.split_first().ok_or(ParseError::Empty)?;
+    let cmd = cmd.trim();
+    if cmd.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    if cmd.starts_with('/') {
+        return Ok(Shorthand::Miio {
+            uri: cmd.to_owned(),
+            data: parse_json(argv)?,
+        });
+    }
+    if cmd.starts_with("prop/") {
+        return Ok(Shorthand::Miot {
+            cmd: cmd.to_owned(),
+            params: parse_json(argv)?,
+        });
+    }
+
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    for item in cmd.split(',') {
+        if item.is_empty() {
+            return Err(ParseError::EmptyItem(cmd.to_owned()));
+        }
+        let (key, value) = match item.split_once('=') {
+            Some((key, value)) => (key, Some(parse_arg(value)?)),
+            None => (item, None),
+        };
+        if key.is_empty() {
+            return Err(ParseError::EmptyKey(item.to_owned()));
+        }
+        keys.push(parse_key(key));
+        values.push(value);
+    }
+
+    let miot = keys.iter().all(|k| matches!(k, PropKey::Miot(..)));
+    if !miot && keys.iter().any(|k| matches!(k, PropKey::Miot(..))) {
+        return Err(ParseError::MixedKeys(cmd.to_owned()));
+    }
+
+    if !argv.is_empty() {
+        if keys.len() != 1 || values[0].is_some() {
+            return Err(ParseError::InvalidCall(cmd.to_owned()));
+        }
+        let args = if argv.len() == 1 && argv[0] == "#NA" {
+            Vec::new()
+        } else {
+            argv.iter()
+                .map(|a| parse_arg(a))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        return Ok(match keys.remove(0) {
+            PropKey::Miot(siid, aiid) => Shorthand::Action { siid, aiid, args },
+            PropKey::Legacy(method) => Shorthand::Home { method, args },
+        });
+    }
+
+    if values.iter().all(Option::is_none) {
+        return Ok(Shorthand::GetProps(keys));
+    }
+    if values.iter().any(Option::is_none) {
+        return Err(ParseError::MixedGetSet(cmd.to_owned()));
+    }
+    Ok(Shorthand::SetProps(
+        keys.into_iter().zip(values.into_iter().flatten()).collect(),
+    ))
+}
+
+/// `siid.piid` 或 `siid-piid`，省略 piid 时默认为 1，否则视为旧版属性名
+fn parse_key(key: &str) -> PropKey {
+    let (siid, iid) = key.split_once(['.', '-']).unwrap_or((key, "1"));
+    match (siid.parse(), iid.parse()) {
+        (Ok(siid), Ok(iid)) => PropKey::Miot(siid, iid),
+        _ => PropKey::Legacy(key.to_owned()),
+    }
+}
+
+fn parse_arg(text: &str) -> Result<Arg, ParseError> {
+    match text.strip_prefix('#') {
+        Some(json) => serde_json::from_str(json)
+            .map(Arg::Value)
+            .map_err(|e| ParseError::InvalidValue(text.to_owned(), e.to_string())),
+        None => Ok(Arg::Text(text.to_owned())),
+    }
+}
+
+fn parse_json(argv: &[String]) -> Result<Value, ParseError> {
+    if argv.is_empty() {
+        return Ok(Value::Null);
+    }
+    let text = argv.join(" ");
+    serde_json::from_str(&text).map_err(|e| ParseError::InvalidValue(text, e.to_string()))
+}
+
+/// 执行简写命令并以 JSON 输出结果
+pub async fn exec(
+    svc: MiIOService,
+    args: &[String],
+    default_device: Option<&str>,
+) -> anyhow::Result<()> {
+    let shorthand = parse(args).map_err(|e| anyhow!("{e}\n\n{USAGE}"))?;
+    let result = match shorthand {
+        Shorthand::Miio { uri, data } => svc.miio_request(&uri, data).await?,
+        Shorthand::Miot { cmd, params } => svc.miot_request(&cmd, params).await?,
+        shorthand => {
+            let device = std::env::var("MI_DID")
+                .ok()
+                .or(default_device.map(str::to_owned))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "MI_DID or profile device is required for shorthand commands\n\n{USAGE}"
+                    )
+                })?;
+            let target = Target::resolve(&svc, &device).await?;
+            exec_device(&svc, &target, shorthand).await?
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
+
+async fn exec_device(
+    svc: &MiIOService,
+    target: &Target,
+    shorthand: Shorthand,
+) -> anyhow::Result<Value> {
+    let did = &target.did;
+    let result = match shorthand {
+        Shorthand::GetProps(keys) => match split_keys(keys) {
+            Ok(iids) => miot_results(svc.miot_get_props(did, iids).await?),
+            Err(names) => svc.home_get_props(did, names).await?,
+        },
+        Shorthand::SetProps(props) => {
+            let spec = target.spec(svc).await;
+            let mut miot = Vec::new();
+            let mut legacy = Vec::new();
+            for (key, arg) in props {
+                match key {
+                    PropKey::Miot(siid, piid) => {
+                        let prop = spec.as_ref().and_then(|s| s.property(siid, piid));
+                        miot.push((siid, piid, to_value(prop, arg)?));
+                    }
+                    PropKey::Legacy(name) => legacy.push((name, to_value(None, arg)?)),
+                }
+            }
+            if legacy.is_empty() {
+                miot_results(svc.miot_set_props(did, miot).await?)
+            } else {
+                json!(svc.home_set_props(did, legacy).await?)
+            }
+        }
+        Shorthand::Action { siid, aiid, args } => {
+            let spec = target.spec(svc).await;
+            let action = spec.as_ref().and_then(|s| s.action(siid, aiid));
+            let args = args
+                .into_iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let prop = action
+                        .and_then(|a| a.r#in.get(i))
+                        .and_then(|piid| spec.as_ref()?.property(siid, *piid));
+                    to_value(prop, arg)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            miot_value(svc.miot_action(did, (siid, aiid), Some(args)).await?)
+        }
+        Shorthand::Home { method, args } => {
+            let params = args
+                .into_iter()
+                .map(|arg| to_value(None, arg))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            svc.home_request(did, &method, json!(params)).await?
+        }
+        Shorthand::Miio { .. } | Shorthand::Miot { .. } => unreachable!(),
+    };
+    Ok(result)
+}
+
+/// 全部为 miot 属性时返回 (siid, piid)，否则返回旧版属性名
+fn split_keys(keys: Vec<PropKey>) -> Result<Vec<(i32, i32)>, Vec<String>> {
+    let mut iids = Vec::new();
+    let mut names = Vec::new();
+    for key in keys {
+        match key {
+            PropKey::Miot(siid, piid) => iids.push((siid, piid)),
+            PropKey::Legacy(name) => names.push(name),
+        }
+    }
+    if names.is_empty() {
+        Ok(iids)
+    } else {
+        Err(names)
+    }
+}
+
+fn to_value(prop: Option<&MiotSpecProperty>, arg: Arg) -> anyhow::Result<Value> {
+    match arg {
+        Arg::Value(value) => Ok(value),
+        Arg::Text(text) => parse_value(prop, &text),
+    }
+}
+
+/// 失败项输出错误码与描述
+fn miot_value(result: MiotResult) -> Value {
+    match result {
+        Ok(value) => value,
+        Err(status) => json!({"code": status.code, "error": status.description()}),
+    }
+}
+
+fn miot_results(results: Vec<MiotResult>) -> Value {
+    results.into_iter().map(miot_value).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_props() {
+        assert_eq!(
+            Ok(Shorthand::GetProps(vec![
+                PropKey::Miot(2, 1),
+                PropKey::Miot(2, 2),
+                PropKey::Miot(3, 1)
+            ])),
+            parse(&args(&["2.1,2-2,3"]))
+        );
+        assert_eq!(
+            Ok(Shorthand::GetProps(vec![
+                PropKey::Legacy("power".into()),
+                PropKey::Legacy("bright".into())
+            ])),
+            parse(&args(&["power,bright"]))
+        );
+        assert_eq!(
+            Ok(Shorthand::SetProps(vec![
+                (PropKey::Miot(2, 1), Arg::Value(json!(true))),
+                (PropKey::Miot(2, 2), Arg::Text("on".into())),
+                (PropKey::Miot(2, 3), Arg::Value(json!(60)))
+            ])),
+            parse(&args(&["2.1=#true,2.2=on,2-3=#60"]))
+        );
+    }
+
+    #[test]
+    fn parse_calls() {
+        assert_eq!(
+            Ok(Shorthand::Action {
+                siid: 5,
+                aiid: 1,
+                args: vec![Arg::Text("hello".into()), Arg::Value(json!(3))]
+            }),
+            parse(&args(&["5-1", "hello", "#3"]))
+        );
+        assert_eq!(
+            Ok(Shorthand::Action {
+                siid: 2,
+                aiid: 1,
+                args: vec![]
+            }),
+            parse(&args(&["2", "#NA"]))
+        );
+        assert_eq!(
+            Ok(Shorthand::Home {
+                method: "set_power".into(),
+                args: vec![Arg::Text("on".into())]
+            }),
+            parse(&args(&["set_power", "on"]))
+        );
+        assert_eq!(
+            Ok(Shorthand::Miio {
+                uri: "/home/device_list".into(),
+                data: json!({"getVirtualModel": false})
+            }),
+            parse(&args(&[
+                "/home/device_list",
+                r#"{"getVirtualModel": false}"#
+            ]))
+        );
+        assert_eq!(
+            Ok(Shorthand::Miot {
+                cmd: "prop/get".into(),
+                params: json!([{"did": "1", "siid": 2, "piid": 1}])
+            }),
+            parse(&args(&[
+                "prop/get",
+                r#"[{"did": "1", "siid": 2, "piid": 1}]"#
+            ]))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Err(ParseError::Empty), parse(&[]));
+        assert!(matches!(
+            parse(&args(&["2.1,,2.2"])),
+            Err(ParseError::EmptyItem(_))
+        ));
+        assert!(matches!(
+            parse(&args(&["=on"])),
+            Err(ParseError::EmptyKey(_))
+        ));
+        assert!(matches!(
+            parse(&args(&["2.1,power"])),
+            Err(ParseError::MixedKeys(_))
+        ));
+        assert!(matches!(
+            parse(&args(&["2.1=on,2.2"])),
+            Err(ParseError::MixedGetSet(_))
+        ));
+        assert!(matches!(
+            parse(&args(&["5.1,5.2", "hello"])),
+            Err(ParseError::InvalidCall(_))
+        ));
+        assert!(matches!(
+            parse(&args(&["2.1=#yes"])),
+            Err(ParseError::InvalidValue(..))
+        ));
+        assert!(matches!(
+            parse(&args(&["/home/device_list", "{"])),
+            Err(ParseError::InvalidValue(..))
+        ));
+    }
+
+    #[test]
+    fn text_value_like_prop_set() {
+        assert_eq!(
+            json!(true),
+            to_value(None, Arg::Text("true".into())).unwrap()
+        );
+        assert_eq!(json!(60), to_value(None, Arg::Text("60".into())).unwrap());
+        assert_eq!(json!("on"), to_value(None, Arg::Text("on".into())).unwrap());
+        assert_eq!(
+            json!("60"),
+            to_value(None, Arg::Value(json!("60"))).unwrap()
+        );
+    }
+}
@@ -1,22 +1,95 @@
-use clap::Parser;
-use mi_service::MiIOService;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use mi_service::{MiIOService, SpecCache};
+use serde_json::Value;
 
 #[derive(Debug, Parser)]
 pub struct Args {
-    #[arg(short, long)]
+    #[arg(short, long, help = "设备型号或urn类型，为空时列出全部型号")]
     r#type: Option<String>,
-    #[arg(short, long)]
+    #[arg(short, long, help = "输出格式: text, python, json, yaml")]
     format: Option<String>,
+    #[arg(long, help = "离线模式，只读取本地缓存", env = "MIOT_SPEC_OFFLINE")]
+    offline: bool,
+    #[arg(long, help = "缓存有效期(秒)", env = "MIOT_SPEC_TTL")]
+    ttl: Option<u64>,
+    #[command(subcommand)]
+    command: Option<SpecCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+enum SpecCommand {
+    #[command(about = "Manage local MIoT spec cache")]
+    Cache {
+        #[command(subcommand)]
+        op: CacheOp,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheOp {
+    #[command(about = "Show cache directory and entries")]
+    Status,
+    #[command(about = "Remove all cached specs")]
+    Clear,
+    #[command(about = "Refresh cached specs")]
+    Refresh {
+        #[arg(long, help = "忽略有效期，强制重新下载")]
+        force: bool,
+    },
 }
 
 impl Args {
-    pub async fn exec(&self, svc: MiIOService) -> anyhow::Result<()> {
-        println!("self: {:?}", self);
-        let spec = svc
-            .miot_spec(self.r#type.as_deref(), self.format.as_deref())
-            .await?;
-        let json_str = serde_json::to_string_pretty(&spec)?;
-        println!("{json_str}");
-        Ok(())
+    pub async fn exec(&self, mut svc: MiIOService) -> anyhow::Result<()> {
+        let mut cache = svc.spec_cache().clone();
+        cache.offline |= self.offline;
+        if let Some(ttl) = self.ttl {
+            cache.ttl = Duration::from_secs(ttl);
+        }
+
+        match &self.command {
+            Some(SpecCommand::Cache { op }) => exec_cache(op, &cache).await,
+            None => {
+                svc.set_spec_cache(cache);
+                let spec = svc
+                    .miot_spec(self.r#type.as_deref(), self.format.as_deref())
+                    .await?;
+                match spec {
+                    Value::String(text) => println!("{}", text.trim_end()),
+                    _ => println!("{}", serde_json::to_string_pretty(&spec)?),
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn exec_cache(op: &CacheOp, cache: &SpecCache) -> anyhow::Result<()> {
+    match op {
+        CacheOp::Status => {
+            let status = cache.status().await?;
+            println!("dir:     {}", status.dir.display());
+            println!("ttl:     {}s", status.ttl);
+            println!("offline: {}", status.offline);
+            let items = status.instances.iter().chain(status.types.iter());
+            for item in items {
+                println!(
+                    "{:<8}{:>10}s  {}",
+                    if item.stale { "stale" } else { "fresh" },
+                    item.age,
+                    item.name
+                );
+            }
+        }
+        CacheOp::Clear => {
+            cache.clear().await?;
+            println!("cleared {}", cache.dir.display());
+        }
+        CacheOp::Refresh { force } => {
+            cache.refresh(*force).await?;
+            println!("refreshed {}", cache.dir.display());
+        }
     }
+    Ok(())
 }
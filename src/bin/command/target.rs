@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use mi_service::{MiIODevice, MiIOService, MiotSpecDetail, MiotSpecProperty};
+use serde_json::Value;
+
+/// 命令行中指定的设备，可以是 did 或设备名称
+pub struct Target {
+    pub did: String,
+    pub device: Option<MiIODevice>,
+}
+
+impl Target {
+    /// 在设备列表中按 did 或名称查找，找不到时纯数字视为 did
+    pub async fn resolve(svc: &MiIOService, target: &str) -> Result<Self> {
+        let devices = svc.devices(None, None, None).await?;
+        let device = match devices.iter().find(|d| d.did == target || d.name == target) {
+            Some(device) => Some(device),
+            None => {
+                let matched: Vec<_> = devices.iter().filter(|d| d.name.contains(target)).collect();
+                if matched.len() > 1 {
+                    let names: Vec<_> = matched.iter().map(|d| d.name.as_str()).collect();
+                    return Err(anyhow!(
+                        "device name {target} is ambiguous: {}",
+                        names.join(", ")
+                    ));
+                }
+                matched.first().copied()
+            }
+        };
+
+        match device {
+            Some(device) => Ok(Self {
+                did: device.did.clone(),
+                device: Some(device.clone()),
+            }),
+            None if target.chars().all(|c| c.is_ascii_digit()) => Ok(Self {
+                did: target.to_owned(),
+                device: None,
+            }),
+            None => Err(anyhow!("device {target} not found")),
+        }
+    }
+
+    /// 设备规格，获取失败时返回 None，参数按文本猜测类型
+    pub async fn spec(&self, svc: &MiIOService) -> Option<MiotSpecDetail> {
+        let model = &self.device.as_ref()?.model;
+        match svc.miot_spec_typed(model).await {
+            Ok(spec) => Some(spec),
+            Err(e) => {
+                warn!("Get spec of {model} failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// 解析 `siid.piid`，省略 piid 时默认为 1
+pub fn parse_iid(key: &str) -> Option<(i32, i32)> {
+    let (siid, iid) = key.split_once('.').unwrap_or((key, "1"));
+    Some((siid.parse().ok()?, iid.parse().ok()?))
+}
+
+/// 按规格转换参数，无规格时按 JSON 解析，失败则视为字符串
+pub fn parse_value(prop: Option<&MiotSpecProperty>, text: &str) -> Result<Value> {
+    match prop {
+        Some(prop) => Ok(prop.parse_value(text)?),
+        None => Ok(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))),
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use clap::{Parser, Subcommand};
+use mi_service::{AccountManager, Error, TokenKey, TokenStore, MIIO_SID, MINA_SID};
+use serde::Serialize;
+
+use super::prompt::read_passphrase;
+
+/// token 子命令，管理保存的登录态
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[command(subcommand)]
+    command: TokenCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum TokenCommand {
+    #[command(about = "Show the saved session with secrets masked")]
+    Show {
+        #[arg(long, help = "以JSON格式输出")]
+        json: bool,
+    },
+    #[command(about = "Check each logged-in sid with a cheap authenticated call")]
+    Check,
+    #[command(about = "Force a re-login of a sid")]
+    Refresh {
+        #[arg(help = "服务ID，如 xiaomiio、micoapi")]
+        sid: String,
+    },
+    #[command(about = "Remove the saved token")]
+    Logout,
+    #[command(about = "Encrypt the saved token with a new key")]
+    Encrypt(NewKey),
+    #[command(about = "Decrypt the saved token to plaintext")]
+    Decrypt,
+    #[command(about = "Re-encrypt the saved token with a new key")]
+    Rekey(NewKey),
+}
+
+/// 新密钥来源，均未指定时从标准输入读取口令
+#[derive(Debug, Parser)]
+struct NewKey {
+    #[arg(long, help = "从文件读取新密钥", conflicts_with = "key_env")]
+    key_file: Option<PathBuf>,
+    #[arg(long, help = "从环境变量读取新密钥")]
+    key_env: Option<String>,
+}
+
+impl NewKey {
+    async fn read(&self) -> anyhow::Result<TokenKey> {
+        if let Some(path) = &self.key_file {
+            return Ok(TokenKey::from_file(path).await?);
+        }
+        if let Some(key) = &self.key_env {
+            let value = std::env::var(key).map_err(|_| anyhow::anyhow!("env {key} is not set"))?;
+            return Ok(TokenKey::parse(&value)?);
+        }
+        let passphrase = read_passphrase("New passphrase: ")?;
+        if read_passphrase("Repeat passphrase: ")? != passphrase {
+            bail!("passphrases do not match");
+        }
+        Ok(TokenKey::Passphrase(passphrase))
+    }
+}
+
+/// token show 的输出，密钥类字段均已打码
+#[derive(Debug, Serialize)]
+struct TokenSummary {
+    location: String,
+    encrypted: bool,
+    user_id: String,
+    device_id: String,
+    pass_token: String,
+    sids: Vec<SidSummary>,
+    cookies: Vec<CookieSummary>,
+}
+
+#[derive(Debug, Serialize)]
+struct SidSummary {
+    sid: String,
+    ssecurity: String,
+    expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CookieSummary {
+    domain: String,
+    name: String,
+    value: String,
+    expires_at: Option<i64>,
+}
+
+impl TokenSummary {
+    fn new(store: &TokenStore) -> Self {
+        let token = &store.token;
+        let mut sids: Vec<_> = token
+            .sid
+            .iter()
+            .map(|(sid, ssecurity)| SidSummary {
+                sid: sid.clone(),
+                ssecurity: mask(ssecurity),
+                expires_at: token.sid_expires.get(sid).map(|at| *at as i64),
+            })
+            .collect();
+        sids.sort_by(|a, b| a.sid.cmp(&b.sid));
+        let cookies = token
+            .cookie_infos()
+            .into_iter()
+            .map(|c| CookieSummary {
+                value: mask(&c.value),
+                domain: c.domain,
+                name: c.name,
+                expires_at: c.expires_at,
+            })
+            .collect();
+        Self {
+            location: store.backend().describe(),
+            encrypted: store.is_encrypted(),
+            user_id: token.user_id.clone(),
+            device_id: token.device_id.clone(),
+            pass_token: mask(&token.pass_token),
+            sids,
+            cookies,
+        }
+    }
+
+    fn print(&self) {
+        let state = if self.encrypted {
+            "encrypted"
+        } else {
+            "plaintext"
+        };
+        println!("location:   {} ({state})", self.location);
+        println!("user id:    {}", self.user_id);
+        println!("device id:  {}", self.device_id);
+        println!("pass token: {}", self.pass_token);
+        println!();
+        let now = now();
+        println!("{:<16}{:<16}SSECURITY", "SID", "EXPIRES");
+        for sid in &self.sids {
+            let expires = match sid.expires_at {
+                Some(at) => format_expiry(Some(at), now),
+                None => "unknown".to_owned(),
+            };
+            println!("{:<16}{:<16}{}", sid.sid, expires, sid.ssecurity);
+        }
+        println!();
+        println!("{:<24}{:<20}{:<16}VALUE", "DOMAIN", "NAME", "EXPIRES");
+        for cookie in &self.cookies {
+            println!(
+                "{:<24}{:<20}{:<16}{}",
+                cookie.domain,
+                cookie.name,
+                format_expiry(cookie.expires_at, now),
+                cookie.value
+            );
+        }
+    }
+}
+
+/// 只保留开头4个字符
+fn mask(secret: &str) -> String {
+    if secret.is_empty() {
+        return String::new();
+    }
+    if secret.chars().count() <= 8 {
+        return "****".to_owned();
+    }
+    let head: String = secret.chars().take(4).collect();
+    format!("{head}****")
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// 剩余有效期，如 `29d 23h`、`expired`，会话 cookie 为 `session`
+fn format_expiry(expires_at: Option<i64>, now: i64) -> String {
+    let Some(at) = expires_at else {
+        return "session".to_owned();
+    };
+    let left = at - now;
+    match left {
+        ..=0 => "expired".to_owned(),
+        1..3600 => format!("{}m", left / 60),
+        3600..86400 => format!("{}h {}m", left / 3600, left % 3600 / 60),
+        _ => format!("{}d {}h", left / 86400, left % 86400 / 3600),
+    }
+}
+
+impl Args {
+    /// 当前密钥取自 profile 配置或 MI_TOKEN_KEY/MI_TOKEN_KEY_FILE
+    pub async fn exec(
+        &self,
+        manager: &AccountManager,
+        profile: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut store = manager.token_store(profile).await?;
+        let location = store.backend().describe();
+        if store.backend().load().await?.is_none() {
+            bail!("no token saved in {location}, login first");
+        }
+
+        match &self.command {
+            TokenCommand::Show { json } => {
+                let summary = TokenSummary::new(&store);
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                } else {
+                    summary.print();
+                }
+                return Ok(());
+            }
+            TokenCommand::Check => return check(manager, profile, &store).await,
+            TokenCommand::Refresh { sid } => {
+                let account = manager.account(profile).await?;
+                let mut account = account.lock().await;
+                account.invalidate(sid).await?;
+                account.login(sid).await?;
+                println!("refreshed {sid}");
+                return Ok(());
+            }
+            TokenCommand::Logout => {
+                store.clean().await?;
+                println!("logged out, removed {location}");
+                return Ok(());
+            }
+            TokenCommand::Encrypt(key) => {
+                store.set_key(Some(&key.read().await?))?;
+            }
+            TokenCommand::Decrypt => {
+                if !store.is_encrypted() {
+                    bail!("token in {location} is not encrypted");
+                }
+                store.set_key(None)?;
+            }
+            TokenCommand::Rekey(key) => {
+                if !store.is_encrypted() {
+                    bail!("token in {location} is not encrypted, use token encrypt");
+                }
+                store.set_key(Some(&key.read().await?))?;
+            }
+        }
+        store.save().await?;
+
+        let state = if store.is_encrypted() {
+            "encrypted"
+        } else {
+            "plaintext"
+        };
+        println!("token saved to {location} ({state})");
+        Ok(())
+    }
+}
+
+/// 对已登录的 sid 各发送一次请求，失效时不重新登录
+async fn check(
+    manager: &AccountManager,
+    profile: Option<&str>,
+    store: &TokenStore,
+) -> anyhow::Result<()> {
+    let mut sids: Vec<_> = store.token.sid.keys().cloned().collect();
+    if sids.is_empty() {
+        bail!("no sid logged in, login first");
+    }
+    sids.sort();
+
+    let mut failed = 0;
+    for sid in &sids {
+        let result = match sid.as_str() {
+            MIIO_SID => manager.miio(profile).await?.check_session().await,
+            MINA_SID => manager.mina(profile).await?.check_session().await,
+            _ => {
+                println!("{sid:<16}skipped, no check available");
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => println!("{sid:<16}ok"),
+            Err(Error::AuthExpired(e)) => {
+                failed += 1;
+                println!("{sid:<16}expired: {e}");
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{sid:<16}error: {e}");
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} sids failed", sids.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_and_expiry() {
+        assert_eq!(mask(""), "");
+        assert_eq!(mask("short"), "****");
+        assert_eq!(mask("V1:abcdefghijkl"), "V1:a****");
+        assert_eq!(format_expiry(None, 0), "session");
+        assert_eq!(format_expiry(Some(100), 200), "expired");
+        assert_eq!(format_expiry(Some(7260), 0), "2h 1m");
+        assert_eq!(format_expiry(Some(2 * 86400 + 3600), 0), "2d 1h");
+    }
+}
@@ -1,11 +1,262 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde_json::Value;
 use thiserror::Error;
 
+use crate::account::LoginChallenge;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// MIoT 单项结果，成功为属性值或动作输出
+pub type MiotResult = Result<Value, MiotStatus>;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
+    /// 网络传输错误(连接失败、超时等)，通常可以重试
+    #[error("Transport Error: request url {url} failed: {source}")]
+    Transport {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// HTTP 状态码非 200
+    #[error("HTTP Error: request url {url} status code {status}, message: {body}")]
+    Http {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+    /// 小米接口返回的业务错误
+    #[error("API Error: code {code}, message: {message}")]
+    Api { code: i32, message: String },
+    /// 登录态失效且无法自动重新登录
+    #[error("Auth Expired: {0}")]
+    AuthExpired(String),
+    /// passToken 缺失或失效且没有密码，需要重新用密码或扫码登录
+    #[error("Re-authentication Required: {0}")]
+    ReauthRequired(String),
+    /// 需要短信/邮件安全验证
+    #[error("Verification Required: {}", .0.verify_url)]
+    VerificationRequired(Box<LoginChallenge>),
+    /// 需要图形验证码
+    #[error("Captcha Required: {0}")]
+    CaptchaRequired(String),
+    /// 响应无法解析，body 为原始内容
+    #[error("Deserialize Error: {source}, body: {body}")]
+    Deserialize {
+        body: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// 设备离线或局域网内无响应
+    #[error("Device Offline: {0}")]
+    DeviceOffline(String),
+    /// MIoT 属性/动作返回的错误码
+    #[error("MIoT Error: device {did}: {status}")]
+    Miot { did: String, status: MiotStatus },
     #[error("ServiceLogin Error: {0}")]
     ServiceLogin(String),
     #[error("SecurityTokenService Error: {0}")]
     SecurityTokenService(String),
-    #[error("Request Error: {0}")]
-    Request(String),
+    #[error("Verification Error: {0}")]
+    Verification(String),
+    #[error("Captcha Error: {0}")]
+    Captcha(String),
+    /// MIoT 规格查询或缓存错误
+    #[error("Spec Error: {0}")]
+    Spec(String),
+    /// miIO 局域网协议错误
+    #[error("Local Error: {0}")]
+    Local(String),
+    /// Token 读取、解析或保存失败
+    #[error("Token Store Error: {0}")]
+    TokenStore(String),
+    /// 多账号配置文件错误
+    #[error("Config Error: {0}")]
+    Config(String),
+    #[error("Invalid Argument: {0}")]
+    InvalidArgument(String),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    pub(crate) fn transport(url: impl Into<String>, source: reqwest::Error) -> Self {
+        Self::Transport {
+            url: url.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn deserialize(body: impl Into<String>, source: serde_json::Error) -> Self {
+        Self::Deserialize {
+            body: body.into(),
+            source,
+        }
+    }
+
+    /// 单项 MIoT 结果转换为 [`Error::Miot`]
+    pub(crate) fn miot(did: &str, item: Option<MiotResult>) -> Result<Value> {
+        match item {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(status)) => Err(Self::Miot {
+                did: did.to_owned(),
+                status,
+            }),
+            None => Err(Self::Api {
+                code: -1,
+                message: format!("empty miot result for device {did}"),
+            }),
+        }
+    }
+
+    /// 是否需要用户重新登录，无密码模式下 passToken 失效时返回
+    pub fn is_reauth_required(&self) -> bool {
+        matches!(self, Self::ReauthRequired(_))
+    }
+
+    /// 是否为可重试的网络传输错误
+    pub fn is_transport(&self) -> bool {
+        matches!(self, Self::Transport { .. })
+    }
+}
+
+/// MIoT 错误码分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MiotCategory {
+    /// 成功或已受理
+    Success,
+    /// 设备离线、忙碌或超时
+    Device,
+    /// 设备、服务、属性或动作不存在
+    NotFound,
+    /// 不可读、不可写或无权限
+    Access,
+    /// 属性值或动作参数错误
+    Value,
+    /// 设备或云端内部错误
+    Internal,
+    Unknown,
+}
+
+/// MIoT 属性/动作的结果码，`miot_get_props` 等批量接口逐项返回
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MiotStatus {
+    pub code: i32,
+}
+
+impl MiotStatus {
+    /// 批量结果中缺少请求的某一项
+    pub const NO_RESULT: MiotStatus = MiotStatus { code: -1 };
+
+    pub fn new(code: i32) -> Self {
+        Self { code }
+    }
+
+    /// 按 siid/piid 在批量结果中查找对应项，找不到时返回 [`MiotStatus::NO_RESULT`]
+    pub(crate) fn find_item(items: &[Value], siid: i32, piid: i32) -> Result<&Value, MiotStatus> {
+        items
+            .iter()
+            .find(|it| it["siid"] == siid && it["piid"] == piid)
+            .ok_or(Self::NO_RESULT)
+    }
+
+    /// 从结果项的 `code` 字段解析，缺失时视为未知错误
+    pub(crate) fn from_item(item: &Value) -> Self {
+        Self::new(item["code"].as_i64().unwrap_or(-1) as i32)
+    }
+
+    /// 0 为成功，1 为已受理但尚未完成
+    pub fn is_success(&self) -> bool {
+        matches!(self.code, 0 | 1)
+    }
+
+    /// 成功时返回 value，否则返回错误码
+    pub fn into_result<T>(self, value: T) -> Result<T, MiotStatus> {
+        if self.is_success() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+
+    pub fn category(&self) -> MiotCategory {
+        self.describe().0
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.describe().1
+    }
+
+    fn describe(&self) -> (MiotCategory, &'static str) {
+        use MiotCategory::*;
+        match self.code {
+            0 => (Success, "success"),
+            1 => (Success, "accepted, operation in progress"),
+            -1 => (Unknown, "no result returned"),
+            // 局域网 miIO 协议返回的短错误码
+            -4001 => (Access, "property not readable"),
+            -4002 => (Access, "property not writable"),
+            -4003 => (NotFound, "property, action or event not found"),
+            -4004 => (Internal, "device internal error"),
+            -4005 => (Value, "invalid property value"),
+            -4006 => (Value, "invalid action arguments"),
+            -4007 => (NotFound, "invalid did"),
+            -704010000 => (Access, "unauthorized, device may have been removed"),
+            -704042011 => (Device, "device offline"),
+            -704053036 => (Access, "property not writable"),
+            -704083036 => (Device, "device operation timed out"),
+            -704090001 => (NotFound, "device not found"),
+            -704220043 => (Value, "value out of range"),
+            // 云端错误码 -70xxxxyyy，末三位为错误类型
+            -799999999..=-700000000 => match -self.code % 1000 {
+                2 => (NotFound, "service not found"),
+                3 => (NotFound, "property not found"),
+                4 => (NotFound, "event not found"),
+                5 => (NotFound, "action not found"),
+                11 => (Device, "device offline"),
+                13 => (Access, "property not readable"),
+                23 => (Access, "property not writable"),
+                33 => (Access, "property not notifiable"),
+                35 => (Value, "invalid value"),
+                36 => (Device, "device operation failed"),
+                43 => (Value, "value out of range"),
+                _ => (Unknown, "unknown MIoT error"),
+            },
+            _ => (Unknown, "unknown MIoT error"),
+        }
+    }
+}
+
+impl fmt::Display for MiotStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.description(), self.code)
+    }
+}
+
+impl std::error::Error for MiotStatus {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn miot_status() {
+        let offline = MiotStatus::new(-704042011);
+        assert_eq!(MiotCategory::Device, offline.category());
+        assert_eq!("device offline (-704042011)", offline.to_string());
+        assert_eq!(MiotCategory::Access, MiotStatus::new(-704053036).category());
+        assert_eq!(
+            "value out of range",
+            MiotStatus::new(-704220043).description()
+        );
+        assert_eq!(
+            MiotCategory::NotFound,
+            MiotStatus::new(-704040003).category()
+        );
+        assert!(MiotStatus::new(1).is_success());
+        assert_eq!(Err(offline), offline.into_result(()));
+    }
 }
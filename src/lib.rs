@@ -4,16 +4,34 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilte
 pub const MIIO_SID: &str = "xiaomiio";
 pub const MINA_SID: &str = "micoapi";
 
-pub use account::Account;
+pub use account::{
+    Account, CaptchaFuture, CaptchaSolver, LoginChallenge, QrLogin, VerifyChannel,
+    VerifyCodeProvider, VerifyFuture,
+};
+pub use errors::{Error, MiotCategory, MiotResult, MiotStatus, Result};
+pub use local::{discover, discover_with, join_cloud, DiscoveredDevice, MiIOLocal, MIIO_PORT};
 pub use miio::{MiIOService, SignData};
-pub use mina::MiNaService;
-pub use store::TokenStore;
+pub use mina::{DeviceSelector, MiNaService, PlayOperation, SendOutcome, SendReport};
+pub use profile::{AccountManager, BackendFactory, Profile, ProfileConfig, DEFAULT_PROFILE};
+pub use resp::{
+    AnswerTts, Capabilities, Conversation, ConversationAnswer, MiIODevice, MiNADevice, MiNaDevices,
+    MiotAccess, MiotFormat, MiotSpecAction, MiotSpecDetail, MiotSpecEvent, MiotSpecInstance,
+    MiotSpecProperty, MiotSpecService, PlayState, PlayerStatus, PlayerTrack, ValueList, ValueRange,
+};
+pub use spec::{SpecCache, SpecCacheItem, SpecCacheStatus, SpecFormat};
+pub use store::{
+    CookieInfo, DirectoryBackend, FileBackend, MemoryBackend, TokenBackend, TokenFuture, TokenKey,
+    TokenStore, TOKEN_KEY_ENV, TOKEN_KEY_FILE_ENV,
+};
 
 mod account;
 mod errors;
+mod local;
 mod miio;
 mod mina;
+mod profile;
 mod resp;
+mod spec;
 mod store;
 mod utils;
 
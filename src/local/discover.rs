@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use serde::Serialize;
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::{errors::Result, resp::MiIODevice};
+
+use super::{
+    packet::{self, Header, HEADER_LEN},
+    MIIO_PORT,
+};
+
+/// 局域网中响应 hello 的设备
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredDevice {
+    pub did: String,
+    pub ip: IpAddr,
+    pub stamp: u32,
+    /// hello 响应中携带的 token，仅未配网的设备会返回
+    pub token: Option<String>,
+    /// 以下字段来自云端设备列表
+    pub name: Option<String>,
+    pub model: Option<String>,
+    /// 云端记录的 localip，与 `ip` 不同时说明云端信息已过期
+    pub cloud_ip: Option<String>,
+}
+
+impl DiscoveredDevice {
+    fn from_header(ip: IpAddr, header: &Header) -> Self {
+        let token = if header.checksum.iter().all(|b| *b == 0 || *b == 0xff) {
+            None
+        } else {
+            Some(hex::encode(header.checksum))
+        };
+        Self {
+            did: header.device_id.to_string(),
+            ip,
+            stamp: header.stamp,
+            token,
+            name: None,
+            model: None,
+            cloud_ip: None,
+        }
+    }
+
+    /// 合并云端设备信息，云端 token 优先
+    pub fn merge(&mut self, device: &MiIODevice) {
+        self.name = Some(device.name.clone());
+        self.model = Some(device.model.clone());
+        self.cloud_ip = Some(device.localip.clone());
+        if !device.token.is_empty() {
+            self.token = Some(device.token.clone());
+        }
+    }
+}
+
+/// 按 did 将云端设备列表合并到发现结果中
+pub fn join_cloud(discovered: &mut [DiscoveredDevice], devices: &[MiIODevice]) {
+    let devices: HashMap<&str, &MiIODevice> = devices.iter().map(|d| (d.did.as_str(), d)).collect();
+    for item in discovered {
+        if let Some(device) = devices.get(item.did.as_str()) {
+            item.merge(device);
+        }
+    }
+}
+
+/// 广播 hello 包发现局域网内的 miIO 设备
+pub async fn discover(wait: Duration) -> Result<Vec<DiscoveredDevice>> {
+    discover_with(
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), MIIO_PORT),
+        wait,
+    )
+    .await
+}
+
+/// 向指定地址(广播地址或单个设备)发送 hello 包，收集 wait 时间内的响应
+pub async fn discover_with(target: SocketAddr, wait: Duration) -> Result<Vec<DiscoveredDevice>> {
+    debug!("discover {target} in {wait:?}");
+    let bind = if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.set_broadcast(true)?;
+
+    // UDP 可能丢包，间隔重发几次 hello
+    let hello = packet::hello();
+    let resend = wait / 3;
+    let mut next_send = Instant::now();
+    let deadline = Instant::now() + wait;
+
+    let mut found: Vec<DiscoveredDevice> = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if now >= next_send {
+            socket.send_to(&hello, target).await?;
+            next_send = now + resend;
+        }
+
+        let remaining = next_send.min(deadline).saturating_duration_since(now);
+        let (len, peer) = match timeout(remaining, socket.recv_from(&mut buf)).await {
+            Ok(res) => res?,
+            Err(_) => continue,
+        };
+        if len != HEADER_LEN {
+            continue;
+        }
+        let Ok(header) = Header::parse(&buf[..len]) else {
+            continue;
+        };
+        // 忽略本机发出的广播包
+        if header.device_id == u32::MAX {
+            continue;
+        }
+        if !found.iter().any(|d| d.ip == peer.ip()) {
+            debug!("discover device {} at {}", header.device_id, peer.ip());
+            found.push(DiscoveredDevice::from_header(peer.ip(), &header));
+        }
+    }
+
+    found.sort_by_key(|d| d.ip);
+    Ok(found)
+}
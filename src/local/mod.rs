@@ -0,0 +1,533 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::{net::UdpSocket, sync::Mutex, time::timeout};
+
+use crate::{
+    errors::{Error, MiotResult, MiotStatus, Result},
+    resp::MiIODevice,
+};
+
+use packet::{Codec, Header, HEADER_LEN};
+
+pub use discover::{discover, discover_with, join_cloud, DiscoveredDevice};
+
+mod discover;
+mod packet;
+
+/// miIO 局域网协议端口
+pub const MIIO_PORT: u16 = 54321;
+
+/// 局域网 miIO 客户端，通过 UDP 54321 端口直接控制设备，不依赖小米云
+pub struct MiIOLocal {
+    addr: SocketAddr,
+    codec: Codec,
+    timeout: Duration,
+    retries: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    socket: UdpSocket,
+    session: Option<Session>,
+    next_id: u32,
+}
+
+/// hello 握手得到的设备信息
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    device_id: u32,
+    stamp: u32,
+    received_at: Instant,
+}
+
+impl Session {
+    /// 设备时间戳需随本地时间推移，否则设备会丢弃请求
+    fn stamp(&self) -> u32 {
+        self.stamp
+            .wrapping_add(self.received_at.elapsed().as_secs() as u32)
+    }
+}
+
+impl MiIOLocal {
+    /// addr 为设备 IP，可带端口，如 `192.168.1.10` 或 `192.168.1.10:54321`
+    pub async fn new(addr: &str, token: &str) -> Result<Self> {
+        let addr = match addr.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => SocketAddr::new(
+                addr.parse::<IpAddr>().map_err(|e| {
+                    Error::InvalidArgument(format!("invalid device address {addr}: {e}"))
+                })?,
+                MIIO_PORT,
+            ),
+        };
+        let bind = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(addr).await?;
+
+        Ok(Self {
+            addr,
+            codec: Codec::new(token)?,
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            state: Mutex::new(State {
+                socket,
+                session: None,
+                next_id: rand::random::<u16>() as u32,
+            }),
+        })
+    }
+
+    /// 使用云端设备列表中的 `localip` 与 `token` 创建
+    pub async fn from_device(device: &MiIODevice) -> Result<Self> {
+        if device.localip.is_empty() || device.token.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "device {} has no local ip or token",
+                device.did
+            )));
+        }
+        Self::new(&device.localip, &device.token).await
+    }
+
+    /// 单次请求的超时时间，默认5秒
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// 超时后重新握手并重试的次数，默认2次
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 发送 miIO 请求，返回 `result` 字段
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        debug!("MiIOLocal::request {} {method}", self.addr);
+        let mut state = self.state.lock().await;
+        let mut last_err = Error::DeviceOffline(self.addr.to_string());
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                debug!("MiIOLocal::request retry {attempt}: {last_err}");
+            }
+            let session = match state.session {
+                Some(session) => session,
+                None => match self.handshake(&state.socket).await {
+                    Ok(session) => *state.session.insert(session),
+                    Err(e) => {
+                        last_err = e;
+                        continue;
+                    }
+                },
+            };
+
+            state.next_id = state.next_id.wrapping_add(1);
+            let id = state.next_id;
+            let payload = serde_json::to_vec(&json!({
+                "id": id,
+                "method": method,
+                "params": params,
+            }))
+            .map_err(|e| Error::InvalidArgument(e.to_string()))?;
+            let packet = self
+                .codec
+                .encode(session.device_id, session.stamp(), &payload);
+            state.socket.send(&packet).await?;
+
+            match self.recv_response(&state.socket, id).await {
+                Ok(resp) => {
+                    if let Some(error) = resp.get("error") {
+                        return Err(Error::Api {
+                            code: error["code"].as_i64().unwrap_or(-1) as i32,
+                            message: error["message"]
+                                .as_str()
+                                .map(str::to_owned)
+                                .unwrap_or_else(|| error.to_string()),
+                        });
+                    }
+                    return Ok(resp["result"].clone());
+                }
+                Err(e) => {
+                    // 超时或响应异常时重新握手
+                    state.session = None;
+                    last_err = e;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    async fn handshake(&self, socket: &UdpSocket) -> Result<Session> {
+        debug!("MiIOLocal::handshake {}", self.addr);
+        socket.send(&packet::hello()).await?;
+
+        let mut buf = [0u8; 1024];
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let len = timeout(remaining, socket.recv(&mut buf))
+                .await
+                .map_err(|_| Error::DeviceOffline(self.addr.to_string()))??;
+            // 丢弃之前超时请求的迟到响应
+            if len != HEADER_LEN {
+                continue;
+            }
+            let header = Header::parse(&buf[..len])?;
+            return Ok(Session {
+                device_id: header.device_id,
+                stamp: header.stamp,
+                received_at: Instant::now(),
+            });
+        }
+    }
+
+    async fn recv_response(&self, socket: &UdpSocket, id: u32) -> Result<Value> {
+        let mut buf = vec![0u8; 65535];
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let len = timeout(remaining, socket.recv(&mut buf))
+                .await
+                .map_err(|_| Error::DeviceOffline(self.addr.to_string()))??;
+
+            // 丢弃无法解析的数据包，例如之前会话的迟到响应
+            let payload = match self.codec.decode(&buf[..len]) {
+                Ok((_, payload)) => payload,
+                Err(e) => {
+                    warn!("Ignore undecodable miio packet from {}: {e}", self.addr);
+                    continue;
+                }
+            };
+            // 部分设备会在 JSON 末尾附加 \0
+            let end = payload
+                .iter()
+                .rposition(|b| *b != 0)
+                .map(|i| i + 1)
+                .unwrap_or_default();
+            let resp: Value = match serde_json::from_slice(&payload[..end]) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    warn!(
+                        "Ignore invalid miio response {}: {e}",
+                        String::from_utf8_lossy(&payload[..end])
+                    );
+                    continue;
+                }
+            };
+            if resp["id"] == id {
+                return Ok(resp);
+            }
+            warn!("Ignore miio response with id {}, expect {id}", resp["id"]);
+        }
+    }
+
+    /// 当前会话的设备 did，未握手时先握手
+    async fn did(&self) -> Result<String> {
+        let mut state = self.state.lock().await;
+        let session = match state.session {
+            Some(session) => session,
+            None => {
+                let session = self.handshake(&state.socket).await?;
+                *state.session.insert(session)
+            }
+        };
+        Ok(session.device_id.to_string())
+    }
+
+    /// 设备信息，`miIO.info`
+    pub async fn info(&self) -> Result<Value> {
+        self.request("miIO.info", json!([])).await
+    }
+
+    pub async fn get_props(&self, props: Vec<String>) -> Result<Value> {
+        debug!("MiIOLocal::get_props");
+        self.request("get_prop", json!(props)).await
+    }
+
+    pub async fn get_prop(&self, prop: &str) -> Result<Value> {
+        debug!("MiIOLocal::get_prop");
+        let result = self.get_props(vec![prop.to_owned()]).await?;
+        Ok(result[0].clone())
+    }
+
+    pub async fn set_props(&self, props: Vec<(String, Value)>) -> Result<Vec<i32>> {
+        debug!("MiIOLocal::set_props");
+        let mut results = Vec::new();
+        for (prop, value) in props {
+            let result = self.set_prop(&prop, value).await?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    pub async fn set_prop(&self, prop: &str, value: Value) -> Result<i32> {
+        debug!("MiIOLocal::set_prop");
+        let value = match value {
+            Value::Array(_) => value,
+            _ => json!([value]),
+        };
+
+        let result = self.request(&format!("set_{}", prop), value).await?;
+        Ok(if result[0] == "ok" {
+            0
+        } else {
+            result[0].as_i64().unwrap_or(-1) as i32
+        })
+    }
+
+    /// 批量读取属性，逐项返回属性值或错误码
+    pub async fn miot_get_props(&self, iids: Vec<(i32, i32)>) -> Result<Vec<MiotResult>> {
+        debug!("MiIOLocal::miot_get_props");
+        let did = self.did().await?;
+        let params: Vec<Value> = iids
+            .iter()
+            .map(|(siid, piid)| {
+                json!({
+                    "did": did,
+                    "siid": siid,
+                    "piid": piid
+                })
+            })
+            .collect();
+
+        let result = self.request("get_properties", json!(params)).await?;
+        let result = result
+            .as_array()
+            .ok_or_else(|| Error::Local(format!("unexpected get_properties result: {result}")))?;
+
+        Ok(iids
+            .into_iter()
+            .map(|(siid, piid)| {
+                MiotStatus::find_item(result, siid, piid)
+                    .and_then(|it| MiotStatus::from_item(it).into_result(it["value"].clone()))
+            })
+            .collect())
+    }
+
+    /// 批量设置属性，成功项返回写入的值
+    pub async fn miot_set_props(&self, props: Vec<(i32, i32, Value)>) -> Result<Vec<MiotResult>> {
+        debug!("MiIOLocal::miot_set_props");
+        let did = self.did().await?;
+        let params: Vec<Value> = props
+            .iter()
+            .map(|(siid, piid, value)| {
+                json!({
+                    "did": did,
+                    "siid": siid,
+                    "piid": piid,
+                    "value": value
+                })
+            })
+            .collect();
+
+        let result = self.request("set_properties", json!(params)).await?;
+        let result = result
+            .as_array()
+            .ok_or_else(|| Error::Local(format!("unexpected set_properties result: {result}")))?;
+
+        Ok(props
+            .into_iter()
+            .map(|(siid, piid, value)| {
+                MiotStatus::find_item(result, siid, piid)
+                    .and_then(|it| MiotStatus::from_item(it).into_result(value))
+            })
+            .collect())
+    }
+
+    pub async fn miot_get_prop(&self, iid: (i32, i32)) -> Result<Value> {
+        debug!("MiIOLocal::miot_get_prop");
+        let mut props = self.miot_get_props(vec![iid]).await?;
+        Error::miot(&self.did().await?, props.pop())
+    }
+
+    pub async fn miot_set_prop(&self, iid: (i32, i32), value: Value) -> Result<()> {
+        debug!("MiIOLocal::miot_set_prop");
+        let mut results = self.miot_set_props(vec![(iid.0, iid.1, value)]).await?;
+        Error::miot(&self.did().await?, results.pop()).map(|_| ())
+    }
+
+    /// 执行动作，成功时返回动作的 `out` 参数
+    pub async fn miot_action(
+        &self,
+        iid: (i32, i32),
+        args: Option<Vec<Value>>,
+    ) -> Result<MiotResult> {
+        debug!("MiIOLocal::miot_action");
+        let did = self.did().await?;
+        let params = json!({
+            "did": did,
+            "siid": iid.0,
+            "aiid": iid.1,
+            "in": args.unwrap_or_default()
+        });
+
+        let result = self.request("action", params).await?;
+        Ok(MiotStatus::from_item(&result).into_result(result["out"].clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "00112233445566778899aabbccddeeff";
+    const DEVICE_ID: u32 = 0x0123_4567;
+
+    /// 模拟设备: 响应 hello，解密请求后按 method 返回结果
+    async fn fake_device() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let codec = Codec::new(TOKEN).unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65535];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                if buf[..len] == packet::hello() {
+                    let mut resp = packet::hello();
+                    resp[4..8].copy_from_slice(&0u32.to_be_bytes());
+                    resp[8..12].copy_from_slice(&DEVICE_ID.to_be_bytes());
+                    resp[12..16].copy_from_slice(&1000u32.to_be_bytes());
+                    socket.send_to(&resp, peer).await.unwrap();
+                    continue;
+                }
+
+                let (header, payload) = codec.decode(&buf[..len]).unwrap();
+                assert_eq!(DEVICE_ID, header.device_id);
+                assert!(header.stamp >= 1000);
+                let req: Value = serde_json::from_slice(&payload).unwrap();
+                let result = match req["method"].as_str().unwrap() {
+                    "get_prop" => json!(["on", 50]),
+                    "set_power" => json!(["ok"]),
+                    "get_properties" => json!(req["params"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|p| {
+                            if p["piid"] == 1 {
+                                json!({"did": p["did"], "siid": p["siid"], "piid": 1, "code": 0, "value": true})
+                            } else {
+                                json!({"did": p["did"], "siid": p["siid"], "piid": p["piid"], "code": -4003})
+                            }
+                        })
+                        .collect::<Vec<_>>()),
+                    // 只返回第一项的结果
+                    "set_properties" => {
+                        let p = &req["params"][0];
+                        json!([{"did": p["did"], "siid": p["siid"], "piid": p["piid"], "code": 0}])
+                    }
+                    "action" => json!({"code": 0, "out": []}),
+                    _ => {
+                        let resp = json!({"id": req["id"], "error": {"code": -9999, "message": "unknown method"}});
+                        let packet = codec.encode(DEVICE_ID, 1000, resp.to_string().as_bytes());
+                        socket.send_to(&packet, peer).await.unwrap();
+                        continue;
+                    }
+                };
+                let mut resp = json!({"id": req["id"], "result": result})
+                    .to_string()
+                    .into_bytes();
+                resp.push(0);
+                // 先发送一个无法解密的数据包，请求应忽略它继续等待
+                let junk = Codec::new("ffeeddccbbaa99887766554433221100").unwrap();
+                let packet = junk.encode(DEVICE_ID, 1000, &resp);
+                socket.send_to(&packet, peer).await.unwrap();
+                let packet = codec.encode(DEVICE_ID, 1000, &resp);
+                socket.send_to(&packet, peer).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn codec_roundtrip() {
+        let codec = Codec::new(TOKEN).unwrap();
+        let packet = codec.encode(DEVICE_ID, 42, br#"{"id":1}"#);
+        assert_eq!(packet.len(), HEADER_LEN + 16);
+        let (header, payload) = codec.decode(&packet).unwrap();
+        assert_eq!(DEVICE_ID, header.device_id);
+        assert_eq!(42, header.stamp);
+        assert_eq!(br#"{"id":1}"#.to_vec(), payload);
+
+        let mut broken = packet.clone();
+        broken[40] ^= 0xff;
+        assert!(codec.decode(&broken).is_err());
+        assert!(Codec::new("xyz").is_err());
+    }
+
+    #[tokio::test]
+    async fn request_fake_device() {
+        let addr = fake_device().await;
+        let local = MiIOLocal::new(&addr.to_string(), TOKEN).await.unwrap();
+
+        assert_eq!(
+            json!(["on", 50]),
+            local
+                .get_props(vec!["power".into(), "bright".into()])
+                .await
+                .unwrap()
+        );
+        assert_eq!(0, local.set_prop("power", json!("on")).await.unwrap());
+        assert_eq!(
+            vec![Ok(json!(true)), Err(MiotStatus::new(-4003))],
+            local.miot_get_props(vec![(2, 1), (2, 2)]).await.unwrap()
+        );
+        assert_eq!(
+            vec![Ok(json!(false))],
+            local
+                .miot_set_props(vec![(2, 1, json!(false))])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            vec![Ok(json!(1)), Err(MiotStatus::NO_RESULT)],
+            local
+                .miot_set_props(vec![(2, 2, json!(1)), (2, 3, json!(2))])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Ok(json!([])),
+            local.miot_action((2, 1), None).await.unwrap()
+        );
+        assert!(local.request("unknown", json!([])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn discover_fake_device() {
+        let addr = fake_device().await;
+        let mut found = discover_with(addr, Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(1, found.len());
+        assert_eq!(DEVICE_ID.to_string(), found[0].did);
+        assert_eq!(None, found[0].token);
+
+        let cloud: MiIODevice = serde_json::from_value(json!({
+            "did": DEVICE_ID.to_string(), "token": TOKEN, "longitude": "", "latitude": "",
+            "name": "Desk Lamp", "pid": "0", "localip": "192.168.1.20", "mac": "", "ssid": "",
+            "bssid": "", "parent_id": "", "parent_model": "", "show_mode": 1,
+            "model": "yeelink.light.lamp1", "adminFlag": 1, "shareFlag": 0, "permitLevel": 16,
+            "isOnline": true, "desc": "", "extra": {"isSetPincode": 0, "pincodeType": 0,
+            "fw_version": "", "needVerifyCode": 0, "isPasswordEncrypt": 0}, "uid": 1, "pd_id": 1,
+            "password": "", "p2p_id": "", "rssi": 0, "family_id": 0, "reset_flag": 0
+        }))
+        .unwrap();
+        join_cloud(&mut found, &[cloud]);
+        assert_eq!(Some("Desk Lamp"), found[0].name.as_deref());
+        assert_eq!(Some(TOKEN), found[0].token.as_deref());
+    }
+}
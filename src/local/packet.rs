@@ -0,0 +1,139 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+
+use crate::errors::{Error, Result};
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+const MAGIC: u16 = 0x2131;
+pub const HEADER_LEN: usize = 32;
+
+/// hello 握手包: magic + 长度 0x20，其余字节全为 0xff
+pub fn hello() -> [u8; HEADER_LEN] {
+    let mut packet = [0xffu8; HEADER_LEN];
+    packet[..2].copy_from_slice(&MAGIC.to_be_bytes());
+    packet[2..4].copy_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+    packet
+}
+
+/// miIO 数据包头
+///
+/// ```text
+/// 0      2      4          8           12      16         32
+/// | magic| len  | unknown  | device id | stamp | checksum |
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub length: u16,
+    pub unknown: u32,
+    pub device_id: u32,
+    pub stamp: u32,
+    pub checksum: [u8; 16],
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            return Err(Error::Local(format!(
+                "miio packet too short: {} bytes",
+                data.len()
+            )));
+        }
+        let magic = u16::from_be_bytes([data[0], data[1]]);
+        if magic != MAGIC {
+            return Err(Error::Local(format!(
+                "invalid miio packet magic: {magic:#06x}"
+            )));
+        }
+        let length = u16::from_be_bytes([data[2], data[3]]);
+        if length as usize != data.len() {
+            return Err(Error::Local(format!(
+                "miio packet length mismatch: header {length}, received {}",
+                data.len()
+            )));
+        }
+
+        Ok(Self {
+            length,
+            unknown: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            device_id: u32::from_be_bytes(data[8..12].try_into().unwrap()),
+            stamp: u32::from_be_bytes(data[12..16].try_into().unwrap()),
+            checksum: data[16..32].try_into().unwrap(),
+        })
+    }
+}
+
+/// 基于设备 token 的加解密
+///
+/// key = md5(token)，iv = md5(key + token)，AES-128-CBC + PKCS7
+#[derive(Clone)]
+pub struct Codec {
+    token: [u8; 16],
+    key: [u8; 16],
+    iv: [u8; 16],
+}
+
+impl Codec {
+    /// token 为设备的 32 位十六进制字符串
+    pub fn new(token: &str) -> Result<Self> {
+        let token: [u8; 16] = hex::decode(token.trim())
+            .map_err(|e| Error::InvalidArgument(format!("invalid miio token: {e}")))?
+            .try_into()
+            .map_err(|_| Error::InvalidArgument("miio token must be 16 bytes".to_owned()))?;
+        let key = md5::compute(token).0;
+        let mut ctx = md5::Context::new();
+        ctx.consume(key);
+        ctx.consume(token);
+        let iv = ctx.compute().0;
+
+        Ok(Self { token, key, iv })
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        Aes128CbcEnc::new(&self.key.into(), &self.iv.into()).encrypt_padded_vec_mut::<Pkcs7>(plain)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Aes128CbcDec::new(&self.key.into(), &self.iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .map_err(|e| Error::Local(format!("decrypt miio packet failed: {e}")))
+    }
+
+    /// 封装请求包，校验和为 md5(header + token + 密文)
+    pub fn encode(&self, device_id: u32, stamp: u32, payload: &[u8]) -> Vec<u8> {
+        let encrypted = self.encrypt(payload);
+        let length = (HEADER_LEN + encrypted.len()) as u16;
+
+        let mut packet = Vec::with_capacity(length as usize);
+        packet.extend_from_slice(&MAGIC.to_be_bytes());
+        packet.extend_from_slice(&length.to_be_bytes());
+        packet.extend_from_slice(&0u32.to_be_bytes());
+        packet.extend_from_slice(&device_id.to_be_bytes());
+        packet.extend_from_slice(&stamp.to_be_bytes());
+        packet.extend_from_slice(&self.token);
+        packet.extend_from_slice(&encrypted);
+
+        let checksum = md5::compute(&packet).0;
+        packet[16..HEADER_LEN].copy_from_slice(&checksum);
+        packet
+    }
+
+    /// 解析并校验数据包，返回包头与明文
+    pub fn decode(&self, data: &[u8]) -> Result<(Header, Vec<u8>)> {
+        let header = Header::parse(data)?;
+        let mut ctx = md5::Context::new();
+        ctx.consume(&data[..16]);
+        ctx.consume(self.token);
+        ctx.consume(&data[HEADER_LEN..]);
+        if ctx.compute().0 != header.checksum {
+            return Err(Error::Local("miio packet checksum mismatch".to_owned()));
+        }
+
+        let payload = if data.len() > HEADER_LEN {
+            self.decrypt(&data[HEADER_LEN..])?
+        } else {
+            Vec::new()
+        };
+        Ok((header, payload))
+    }
+}
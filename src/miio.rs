@@ -1,11 +1,8 @@
 use std::{
-    env,
-    path::Path,
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD, Engine};
 use hmac::{Hmac, Mac};
 use log::debug;
@@ -14,22 +11,29 @@ use reqwest::header::{HeaderMap, USER_AGENT};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
-use tempfile::tempdir;
-use tokio::{fs, sync::Mutex};
+use tokio::sync::Mutex;
 use url::Url;
 
 use crate::{
-    resp::{MiIODevice, MiIODevices, Response, ResultData},
+    errors::{Error, MiotResult, MiotStatus, Result},
+    resp::{MiIODevice, MiIODevices, MiotSpecDetail, Response, ResultData},
+    spec::{SpecCache, SpecFormat},
     Account, MIIO_SID,
 };
 
 pub struct MiIOService {
     account: Arc<Mutex<Account>>,
     server: String,
+    spec_cache: SpecCache,
 }
 
 impl MiIOService {
     pub fn new(account: Account, region: Option<&str>) -> Self {
+        Self::with_account(Arc::new(Mutex::new(account)), region)
+    }
+
+    /// 与其他服务共享同一账号，各 sid 的 token 保存在同一文件中
+    pub fn with_account(account: Arc<Mutex<Account>>, region: Option<&str>) -> Self {
         let r = region
             .map(|s| {
                 if s == "cn" {
@@ -41,25 +45,55 @@ impl MiIOService {
             .unwrap_or_default();
 
         let server = format!("https://{r}api.io.mi.com/app");
-        // 检查添加PassportDeviceId cookie
-        {
-            let mut store = account.token.cookies.lock().unwrap();
-            let request_url = Url::parse(&server).unwrap();
-            let domain = request_url.domain().unwrap();
-            if !store.contains(domain, "/", "PassportDeviceId") {
-                let cookie = cookie::Cookie::build(("PassportDeviceId", &account.token.device_id))
-                    .domain(domain)
-                    .path("/")
-                    .build();
-                store.insert_raw(&cookie, &request_url).unwrap();
-            }
+        Self {
+            account,
+            server,
+            spec_cache: SpecCache::new(),
         }
+    }
+
+    pub fn account(&self) -> Arc<Mutex<Account>> {
+        self.account.clone()
+    }
 
-        let account = Arc::new(Mutex::new(account));
-        Self { account, server }
+    pub fn spec_cache(&self) -> &SpecCache {
+        &self.spec_cache
+    }
+
+    /// 替换MIoT规格缓存配置，如目录、有效期、离线模式
+    pub fn set_spec_cache(&mut self, spec_cache: SpecCache) {
+        self.spec_cache = spec_cache;
+    }
+
+    /// 检查添加PassportDeviceId cookie
+    fn ensure_device_id(&self, account: &Account) {
+        let mut store = account.token.cookies.lock().unwrap();
+        let request_url = Url::parse(&self.server).unwrap();
+        let domain = request_url.domain().unwrap();
+        if !store.contains(domain, "/", "PassportDeviceId") {
+            let cookie = cookie::Cookie::build(("PassportDeviceId", &account.token.device_id))
+                .domain(domain)
+                .path("/")
+                .build();
+            store.insert_raw(&cookie, &request_url).unwrap();
+        }
     }
 
     async fn request<R, P>(&self, uri: &str, data: P) -> Result<Response<R>>
+    where
+        R: for<'de> Deserialize<'de>,
+        P: Serialize + Clone,
+    {
+        self.request_with(uri, data, None).await
+    }
+
+    /// relogin 为 Some(false) 时登录态失效直接返回 [`Error::AuthExpired`]
+    async fn request_with<R, P>(
+        &self,
+        uri: &str,
+        data: P,
+        relogin: Option<bool>,
+    ) -> Result<Response<R>>
     where
         R: for<'de> Deserialize<'de>,
         P: Serialize + Clone,
@@ -76,16 +110,36 @@ impl MiIOService {
         );
 
         let mut account = self.account.lock().await;
+        self.ensure_device_id(&account);
         let ssecurity = account.get_sid(MIIO_SID).await?;
         let data = sign_data(uri, data, &ssecurity);
 
         let url = format!("{}{uri}", self.server);
         let res = account
-            .request::<R, SignData>(MIIO_SID, &url, data, Some(headers), None)
+            .request::<R, SignData>(MIIO_SID, &url, data, Some(headers), relogin)
             .await?;
         Ok(res)
     }
 
+    /// 检查 xiaomiio 登录态，未登录或失效时返回 [`Error::AuthExpired`]，不会重新登录
+    pub async fn check_session(&self) -> Result<()> {
+        debug!("MiIOService::check_session");
+        if !self.account.lock().await.token.sid.contains_key(MIIO_SID) {
+            return Err(Error::AuthExpired(format!("not logged in to {MIIO_SID}")));
+        }
+        let data = json!({"getVirtualModel": false, "getHuamiDevices": 0, "limit": 1});
+        self.request_with::<Value, _>("/home/device_list", data, Some(false))
+            .await?;
+        Ok(())
+    }
+
+    /// 调用任意 miio 接口，如 `/home/device_list`
+    pub async fn miio_request(&self, uri: &str, data: Value) -> Result<Value> {
+        debug!("MiIOService::miio_request");
+        let resp: Response<Value> = self.request(uri, data).await?;
+        Ok(resp.data)
+    }
+
     pub async fn home_request(&self, did: &str, method: &str, params: Value) -> Result<Value> {
         debug!("MiIOService::home_request");
         let data = Some(json!({
@@ -95,7 +149,7 @@ impl MiIOService {
             "params": params,
         }));
         let resp = self.request(&format!("/home/rpc/{}", did), data).await?;
-        Ok(resp.data)
+        Ok(take_result(resp))
     }
 
     pub async fn home_get_props(&self, did: &str, props: Vec<String>) -> Result<Value> {
@@ -141,14 +195,15 @@ impl MiIOService {
         let resp = self
             .request(&format!("/miotspec/{}", cmd), json!({"params": params}))
             .await?;
-        Ok(resp.data)
+        Ok(take_result(resp))
     }
 
+    /// 批量读取属性，逐项返回属性值或错误码
     pub async fn miot_get_props(
         &self,
         did: &str,
         iids: Vec<(i32, i32)>,
-    ) -> Result<Vec<Option<Value>>> {
+    ) -> Result<Vec<MiotResult>> {
         debug!("MiIOService::miot_get_props");
         let params: Vec<Value> = iids
             .iter()
@@ -162,26 +217,23 @@ impl MiIOService {
             .collect();
 
         let result = self.miot_request("prop/get", json!(params)).await?;
+        let items = miot_items(&result)?;
 
-        Ok(result
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|it| {
-                if it["code"] == 0 {
-                    Some(it["value"].clone())
-                } else {
-                    None
-                }
+        Ok(iids
+            .into_iter()
+            .map(|(siid, piid)| {
+                MiotStatus::find_item(items, siid, piid)
+                    .and_then(|it| MiotStatus::from_item(it).into_result(it["value"].clone()))
             })
             .collect())
     }
 
+    /// 批量设置属性，成功项返回写入的值
     pub async fn miot_set_props(
         &self,
         did: &str,
         props: Vec<(i32, i32, Value)>,
-    ) -> Result<Vec<i32>> {
+    ) -> Result<Vec<MiotResult>> {
         debug!("MiIOService::miot_set_props");
         let params: Vec<Value> = props
             .iter()
@@ -196,35 +248,38 @@ impl MiIOService {
             .collect();
 
         let result = self.miot_request("prop/set", json!(params)).await?;
+        let items = miot_items(&result)?;
 
-        Ok(result
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|it| it["code"].as_i64().unwrap_or(-1) as i32)
+        Ok(props
+            .into_iter()
+            .map(|(siid, piid, value)| {
+                MiotStatus::find_item(items, siid, piid)
+                    .and_then(|it| MiotStatus::from_item(it).into_result(value))
+            })
             .collect())
     }
 
-    pub async fn miot_get_prop(&self, did: &str, iid: (i32, i32)) -> Result<Option<Value>> {
+    pub async fn miot_get_prop(&self, did: &str, iid: (i32, i32)) -> Result<Value> {
         debug!("MiIOService::miot_get_prop");
-        let props = self.miot_get_props(did, vec![iid]).await?;
-        Ok(props[0].clone())
+        let mut props = self.miot_get_props(did, vec![iid]).await?;
+        Error::miot(did, props.pop())
     }
 
-    pub async fn miot_set_prop(&self, did: &str, iid: (i32, i32), value: Value) -> Result<i32> {
+    pub async fn miot_set_prop(&self, did: &str, iid: (i32, i32), value: Value) -> Result<()> {
         debug!("MiIOService::miot_set_prop");
-        let results = self
+        let mut results = self
             .miot_set_props(did, vec![(iid.0, iid.1, value)])
             .await?;
-        Ok(results[0])
+        Error::miot(did, results.pop()).map(|_| ())
     }
 
+    /// 执行动作，成功时返回动作的 `out` 参数
     pub async fn miot_action(
         &self,
         did: &str,
         iid: (i32, i32),
         args: Option<Vec<Value>>,
-    ) -> Result<i32> {
+    ) -> Result<MiotResult> {
         debug!("MiIOService::miot_action");
         let params = json!({
             "did": did,
@@ -234,7 +289,7 @@ impl MiIOService {
         });
 
         let result = self.miot_request("action", params).await?;
-        Ok(result["code"].as_i64().unwrap_or(-1) as i32)
+        Ok(MiotStatus::from_item(&result).into_result(result["out"].clone()))
     }
 
     pub async fn devices(
@@ -267,34 +322,7 @@ impl MiIOService {
         format: Option<&str>,
     ) -> Result<Value> {
         if !type_filter.map(|t| t.starts_with("urn")).unwrap_or(false) {
-            let cache_path = if let Ok(path) = env::var("MIOT_SPEC_PATH") {
-                let temp_dir = Path::new(&path);
-                temp_dir.join("miservice_miot_specs.json")
-            } else {
-                tempdir()?.path().join("miservice_miot_specs.json")
-            };
-
-            let specs = if cache_path.exists() {
-                let content = fs::read_to_string(&cache_path).await?;
-                serde_json::from_str(&content)?
-            } else {
-                let client = reqwest::Client::new();
-                let resp = client
-                    .get("http://miot-spec.org/miot-spec-v2/instances?status=all")
-                    .send()
-                    .await?
-                    .json::<Value>()
-                    .await?;
-
-                let instances = resp["instances"].as_array().unwrap();
-                let mut specs = json!({});
-                for instance in instances {
-                    specs[instance["model"].as_str().unwrap()] = instance["type"].clone();
-                }
-
-                fs::write(&cache_path, serde_json::to_string(&specs)?).await?;
-                specs
-            };
+            let specs = self.miot_spec_instances().await?;
 
             // 根据type_filter过滤规格
             let filtered_specs = if let Some(filter) = type_filter {
@@ -325,25 +353,45 @@ impl MiIOService {
         self.fetch_spec_details(type_filter.unwrap(), format).await
     }
 
+    /// 获取指定型号(或 urn 类型)的结构化规格
+    pub async fn miot_spec_typed(&self, model: &str) -> Result<MiotSpecDetail> {
+        debug!("MiIOService::miot_spec_typed");
+        let spec_type = if model.starts_with("urn") {
+            model.to_owned()
+        } else {
+            let specs = self.miot_spec_instances().await?;
+            specs[model]
+                .as_str()
+                .ok_or_else(|| Error::Spec(format!("no miot spec found for model {model}")))?
+                .to_owned()
+        };
+
+        let value = self.fetch_spec_raw(&spec_type).await?;
+        serde_json::from_value(value.clone()).map_err(|e| Error::deserialize(value.to_string(), e))
+    }
+
+    /// 获取全部型号与规格类型的映射，key: model, value: type
+    async fn miot_spec_instances(&self) -> Result<Value> {
+        Ok(json!(self.spec_cache.instances().await?))
+    }
+
     /// 获取规格详情
-    /// TODO: 实现格式化输出
-    async fn fetch_spec_details(&self, spec_type: &str, _format: Option<&str>) -> Result<Value> {
-        let url = format!(
-            "http://miot-spec.org/miot-spec-v2/instance?type={}",
-            spec_type
-        );
-        let client = reqwest::Client::new();
-        let result = client.get(&url).send().await?.json::<Value>().await?;
+    /// format 为空时返回原始规格，否则返回按 [`SpecFormat`] 渲染后的字符串
+    async fn fetch_spec_details(&self, spec_type: &str, format: Option<&str>) -> Result<Value> {
+        let result = self.fetch_spec_raw(spec_type).await?;
+        match format {
+            Some(f) => {
+                let format: SpecFormat = f.parse()?;
+                let spec: MiotSpecDetail = serde_json::from_value(result.clone())
+                    .map_err(|e| Error::deserialize(result.to_string(), e))?;
+                Ok(Value::String(format.render(&spec)?))
+            }
+            None => Ok(result),
+        }
+    }
 
-        // 这里需要实现格式化输出的逻辑
-        // 根据format参数(python或其他)生成相应格式的输出
-        // 具体实现比较复杂，需要根据实际需求来完成
-        // match format {
-        //     Some(f) if f == "json" => {
-        //     }
-        //     _ => Ok(result),
-        // }
-        Ok(result)
+    async fn fetch_spec_raw(&self, spec_type: &str) -> Result<Value> {
+        self.spec_cache.spec(spec_type).await
     }
 
     // MIOT解码
@@ -351,14 +399,19 @@ impl MiIOService {
         use rc4::{KeyInit, Rc4, StreamCipher};
         use std::io::Read;
 
-        let key = STANDARD.decode(sign_nonce(ssecurity, nonce))?;
-        let mut cipher = Rc4::<rc4::consts::U256>::new_from_slice(key.as_slice())?;
+        let key = STANDARD
+            .decode(sign_nonce(ssecurity, nonce))
+            .map_err(|e| Error::InvalidArgument(format!("decode nonce failed: {e}")))?;
+        let mut cipher = Rc4::<rc4::consts::U256>::new_from_slice(key.as_slice())
+            .map_err(|e| Error::InvalidArgument(format!("invalid rc4 key: {e}")))?;
 
         // Skip first 1024 bytes
         let mut skip = vec![0u8; 1024];
         cipher.apply_keystream(&mut skip);
 
-        let mut decoded = STANDARD.decode(data)?;
+        let mut decoded = STANDARD
+            .decode(data)
+            .map_err(|e| Error::InvalidArgument(format!("decode data failed: {e}")))?;
         cipher.apply_keystream(&mut decoded);
 
         if gzip {
@@ -370,10 +423,27 @@ impl MiIOService {
             }
         }
 
-        Ok(serde_json::from_slice(&decoded)?)
+        serde_json::from_slice(&decoded)
+            .map_err(|e| Error::deserialize(String::from_utf8_lossy(&decoded), e))
+    }
+}
+
+/// 取出响应的 `result` 字段，[`Response`] 展开了 code、message 以外的字段
+fn take_result(resp: Response<Value>) -> Value {
+    match resp.data {
+        Value::Object(mut data) => data.remove("result").unwrap_or_default(),
+        _ => Value::Null,
     }
 }
 
+/// prop/get、prop/set 的结果数组
+fn miot_items(result: &Value) -> Result<&Vec<Value>> {
+    result.as_array().ok_or_else(|| Error::Api {
+        code: -1,
+        message: format!("unexpected miot result: {result}"),
+    })
+}
+
 /// 签名相关的静态方法
 fn sign_nonce(ssecurity: &str, nonce: &str) -> String {
     let mut hasher = Sha256::new();
@@ -402,8 +472,6 @@ pub fn sign_data<T>(uri: &str, data: T, ssecurity: &str) -> Option<SignData>
 where
     T: Serialize + Clone,
 {
-    let data = serde_json::to_string(&data).ok()?;
-    // let data_str = r#"{"getVirtualModel": false, "getHuamiDevices": 0}"#;
     let mut rng = rand::thread_rng();
     let random_bytes: Vec<u8> = (0..8).map(|_| rng.gen()).collect();
 
@@ -413,6 +481,21 @@ where
         .as_secs()
         / 60;
 
+    sign_data_with(uri, data, ssecurity, random_bytes, timestamp)
+}
+
+/// 使用给定的随机数与时间(分钟)签名
+fn sign_data_with<T>(
+    uri: &str,
+    data: T,
+    ssecurity: &str,
+    random_bytes: Vec<u8>,
+    timestamp: u64,
+) -> Option<SignData>
+where
+    T: Serialize,
+{
+    let data = serde_json::to_string(&data).ok()?;
     let mut nonce_data = random_bytes;
     nonce_data.extend_from_slice(&timestamp.to_be_bytes()[4..]);
 
@@ -447,14 +530,7 @@ mod tests {
         });
         let data = serde_json::to_string(&data).unwrap();
         let random: [u8; 8] = [233, 73, 48, 166, 84, 185, 56, 189];
-        let timestamp2 = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            / 60;
-
         let timestamp: u64 = 28958944;
-        println!("timestamp:{timestamp}--timestamp2:{timestamp2}");
         let big_byte_time = &timestamp.to_be_bytes()[4..];
         assert_eq!([1, 185, 224, 224], big_byte_time);
 
@@ -467,7 +543,7 @@ mod tests {
         assert_eq!("86GVzHJQkMjUqxsSphKtd+2c5x9WqhOBdVcUT8is89Q=", snonce);
         let msg = format!("/home/device_list&{}&{}&data={}", snonce, nonce, data);
         assert_eq!(
-            r#"/home/device_list&86GVzHJQkMjUqxsSphKtd+2c5x9WqhOBdVcUT8is89Q=&6UkwplS5OL0BueDg&data={"getVirtualModel": false, "getHuamiDevices": 0}"#,
+            r#"/home/device_list&86GVzHJQkMjUqxsSphKtd+2c5x9WqhOBdVcUT8is89Q=&6UkwplS5OL0BueDg&data={"getHuamiDevices":0,"getVirtualModel":false}"#,
             msg
         );
 
@@ -477,6 +553,74 @@ mod tests {
         mac.update(msg.as_bytes());
         let result = mac.finalize();
         let signature = STANDARD.encode(result.into_bytes());
-        assert_eq!("+4ElcsBEOqEeoBstLDr6VycknHEFTwRScdK7GATrfIs=", signature);
+        assert_eq!("wSFJozJ5gBaPI0h81H3rJwJC9oMU8kvt9Y1oHK2PQr4=", signature);
+    }
+
+    #[test]
+    fn sign_device_list() {
+        let random = vec![233, 73, 48, 166, 84, 185, 56, 189];
+        let data = ParamDeviceList {
+            get_virtual_model: false,
+            get_huami_devices: 0,
+        };
+        let signed = sign_data_with(
+            "/home/device_list",
+            data,
+            "pgnsv9VeDFb1YAi/75n8ew==",
+            random,
+            28958944,
+        )
+        .unwrap();
+        assert_eq!("6UkwplS5OL0BueDg", signed.nonce);
+        assert_eq!(
+            r#"{"getVirtualModel":false,"getHuamiDevices":0}"#,
+            signed.data
+        );
+        assert_eq!(
+            "yukzySNEYxXHs2ku3LWd0TC9AV/2FhvBwZBqFXsn7bI=",
+            signed.signature
+        );
+    }
+
+    #[test]
+    fn miot_responses() {
+        let parse = |s: &str| take_result(serde_json::from_str::<Response<Value>>(s).unwrap());
+
+        let result = parse(
+            r#"{"code":0,"message":"ok","result":[
+                {"did":"123","siid":2,"piid":1,"code":0,"value":true,"updateTime":1700000000,"exe_time":0},
+                {"did":"123","siid":2,"piid":9,"code":-704042011}
+            ]}"#,
+        );
+        let items = miot_items(&result).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            MiotStatus::from_item(&items[0]).into_result(items[0]["value"].clone()),
+            Ok(json!(true))
+        );
+        assert_eq!(MiotStatus::from_item(&items[1]).code, -704042011);
+        assert_eq!(
+            MiotStatus::find_item(items, 2, 9).map(MiotStatus::from_item),
+            Ok(MiotStatus::new(-704042011))
+        );
+        assert_eq!(
+            MiotStatus::find_item(items, 2, 2),
+            Err(MiotStatus::NO_RESULT)
+        );
+
+        let result = parse(
+            r#"{"code":0,"message":"ok","result":[{"did":"123","siid":2,"piid":1,"code":0,"exe_time":0}]}"#,
+        );
+        let items = miot_items(&result).unwrap();
+        assert!(MiotStatus::from_item(&items[0]).is_success());
+
+        let result = parse(
+            r#"{"code":0,"message":"ok","result":{"did":"123","miid":0,"siid":5,"aiid":1,"code":0,"out":["ok"],"exe_time":0}}"#,
+        );
+        assert!(miot_items(&result).is_err());
+        assert_eq!(
+            MiotStatus::from_item(&result).into_result(result["out"].clone()),
+            Ok(json!(["ok"]))
+        );
     }
 }
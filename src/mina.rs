@@ -1,33 +1,153 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{future, stream, Stream};
 
-use anyhow::Result;
-use log::{debug, error};
+use log::{debug, error, warn};
 use reqwest::header::{HeaderMap, USER_AGENT};
+use reqwest_cookie_store::CookieStoreMutex;
 use serde::Deserialize;
 use serde_json::json;
 use tokio::sync::Mutex;
+use url::Url;
 
 use crate::{
     account::Account,
-    resp::{MiNaDevices, Response},
+    errors::{Error, MiotResult, Result},
+    resp::{Conversation, Conversations, MiNADevice, MiNaDevices, PlayerStatus, Response},
     utils::get_random,
-    MINA_SID,
+    MiIOService, MINA_SID,
 };
 
+const MINA_API: &str = "api2.mina.mi.com";
+const USER_PROFILE_API: &str = "userprofile.mina.mi.com";
+/// 轮询对话记录时每次获取的条数
+const WATCH_LIMIT: usize = 10;
+
+/// 选择要发送消息的音箱
+///
+/// 解析规则: all 或 -1 为全部，数字为序号(从1开始)，id:、alias: 前缀分别按 deviceID、别名匹配，
+/// 其余按名称包含匹配
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    All,
+    Index(usize),
+    Id(String),
+    Alias(String),
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// index 从1开始
+    pub fn matches(&self, index: usize, device: &MiNADevice) -> bool {
+        match self {
+            Self::All => true,
+            Self::Index(i) => *i == index,
+            Self::Id(id) => device.id == *id,
+            Self::Alias(alias) => device.alias == *alias,
+            Self::Name(pattern) => device.name.contains(pattern.as_str()),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(Error::InvalidArgument("empty device selector".to_owned()));
+        }
+        Ok(match s {
+            "all" | "-1" => Self::All,
+            _ => {
+                if let Ok(i) = s.parse::<usize>() {
+                    Self::Index(i)
+                } else if let Some(id) = s.strip_prefix("id:") {
+                    Self::Id(id.to_owned())
+                } else if let Some(alias) = s.strip_prefix("alias:") {
+                    Self::Alias(alias.to_owned())
+                } else {
+                    Self::Name(s.to_owned())
+                }
+            }
+        })
+    }
+}
+
+/// 单个音箱的发送结果
+#[derive(Debug)]
+pub enum SendOutcome {
+    Sent,
+    Skipped(String),
+    Failed(Error),
+}
+
+#[derive(Debug)]
+pub struct SendReport {
+    pub device_id: String,
+    pub name: String,
+    pub outcome: SendOutcome,
+}
+
+/// 播放控制，`player_play_operation` 的 action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayOperation {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Prev,
+}
+
+impl PlayOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Play => "play",
+            Self::Pause => "pause",
+            Self::Toggle => "toggle",
+            Self::Stop => "stop",
+            Self::Next => "next",
+            Self::Prev => "prev",
+        }
+    }
+}
+
 pub struct MiNaService {
     account: Arc<Mutex<Account>>,
 }
 
 impl MiNaService {
     pub fn new(account: Account) -> Self {
-        let account = Arc::new(Mutex::new(account));
+        Self::with_account(Arc::new(Mutex::new(account)))
+    }
+
+    /// 与其他服务共享同一账号，各 sid 的 token 保存在同一文件中
+    pub fn with_account(account: Arc<Mutex<Account>>) -> Self {
         Self { account }
     }
 
-    async fn request<T>(
+    pub fn account(&self) -> Arc<Mutex<Account>> {
+        self.account.clone()
+    }
+
+    async fn request<T>(&self, uri: String, data: Option<serde_json::Value>) -> Result<Response<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.request_host(MINA_API, uri, data, None).await
+    }
+
+    async fn request_host<T>(
         &self,
+        host: &str,
         mut uri: String,
         mut data: Option<serde_json::Value>,
+        relogin: Option<bool>,
     ) -> Result<Response<T>>
     where
         T: for<'de> Deserialize<'de>,
@@ -46,18 +166,32 @@ impl MiNaService {
                 "MiHome/6.0.103 (com.xiaomi.mihome; build:6.0.103.1; iOS 14.4.0) Alamofire/6.0.103 MICO/iOSApp/appStore/6.0.103".parse().unwrap(),
             );
 
-        let mut account = self.account.lock().await;
-        let resp = account
-            .request(
-                MINA_SID,
-                &format!("https://api2.mina.mi.com{uri}"),
-                data,
-                Some(headers),
-                None,
-            )
-            .await?;
+        // 只在登录时持有账号锁，send_message 等并发请求可以同时进行
+        Account::request_shared(
+            &self.account,
+            MINA_SID,
+            &format!("https://{host}{uri}"),
+            data,
+            Some(headers),
+            relogin,
+        )
+        .await
+    }
 
-        Ok(resp)
+    /// 检查 micoapi 登录态，未登录或失效时返回 [`Error::AuthExpired`]，不会重新登录
+    pub async fn check_session(&self) -> Result<()> {
+        debug!("MiNaService::check_session");
+        if !self.account.lock().await.token.sid.contains_key(MINA_SID) {
+            return Err(Error::AuthExpired(format!("not logged in to {MINA_SID}")));
+        }
+        self.request_host::<serde_json::Value>(
+            MINA_API,
+            "/admin/v2/device_list?master=0".to_owned(),
+            None,
+            Some(false),
+        )
+        .await?;
+        Ok(())
     }
 
     pub async fn devices(&self, master: Option<usize>) -> Result<MiNaDevices> {
@@ -75,6 +209,26 @@ impl MiNaService {
         Ok(result.data)
     }
 
+    async fn ubus_call(
+        &self,
+        device_id: &str,
+        method: &str,
+        path: &str,
+        message: serde_json::Value,
+    ) -> Result<Response<serde_json::Value>> {
+        debug!("MiNaService::ubus_call");
+        self.request(
+            "/remote/ubus".to_string(),
+            Some(json!({
+                "deviceId": device_id,
+                "message": message.to_string(),
+                "method": method,
+                "path": path,
+            })),
+        )
+        .await
+    }
+
     async fn ubus_request(
         &self,
         device_id: &str,
@@ -83,17 +237,7 @@ impl MiNaService {
         message: serde_json::Value,
     ) -> Result<bool> {
         debug!("MiNaService::ubus_request");
-        let resp: Response<serde_json::Value> = self
-            .request(
-                "/remote/ubus".to_string(),
-                Some(json!({
-                    "deviceId": device_id,
-                    "message": message,
-                    "method": method,
-                    "path": path,
-                })),
-            )
-            .await?;
+        let resp = self.ubus_call(device_id, method, path, message).await?;
         Ok(resp.code == 0)
     }
 
@@ -124,67 +268,454 @@ impl MiNaService {
         .await
     }
 
-    /// 发生消息 或 调整 设备音量
-    pub async fn send_message(
+    pub async fn player_play_operation(
         &self,
-        devices: &[serde_json::Value], // 假设使用 serde_json::Value 来表示设备数组
-        devno: i32,                    // -1/0/1...
-        message: Option<String>,
-        volume: Option<i32>,
+        device_id: &str,
+        operation: PlayOperation,
     ) -> Result<bool> {
-        let mut result = false;
-
-        for (i, device) in devices.iter().enumerate() {
-            // 检查设备条件：devno == -1 或 devno != i+1 或设备支持 yunduantts
-            if devno == -1
-                || devno != (i + 1) as i32
-                || device["capabilities"]["yunduantts"]
-                    .as_bool()
-                    .unwrap_or(false)
+        debug!("MiNaService::player_play_operation");
+        self.ubus_request(
+            device_id,
+            "player_play_operation",
+            "mediaplayer",
+            json!({
+                "action": operation.as_str(),
+                "media": "app_ios",
+            }),
+        )
+        .await
+    }
+
+    /// 播放器状态，包括音量、播放状态与当前曲目
+    pub async fn player_get_status(&self, device_id: &str) -> Result<PlayerStatus> {
+        debug!("MiNaService::player_get_status");
+        let resp = self
+            .ubus_call(
+                device_id,
+                "player_get_play_status",
+                "mediaplayer",
+                json!({
+                    "media": "app_ios",
+                }),
+            )
+            .await?;
+
+        // info 为 JSON 字符串
+        let info = resp.data["data"]["info"].as_str().unwrap_or("{}");
+        serde_json::from_str(info).map_err(|e| Error::deserialize(info, e))
+    }
+
+    /// 播放任意音频地址
+    pub async fn player_play_url(&self, device_id: &str, url: &str) -> Result<bool> {
+        debug!("MiNaService::player_play_url");
+        self.ubus_request(
+            device_id,
+            "player_play_url",
+            "mediaplayer",
+            json!({
+                "url": url,
+                "type": 1,
+                "media": "app_ios",
+            }),
+        )
+        .await
+    }
+
+    /// 设置循环模式，loop_type 与 [`PlayerStatus::loop_type`] 取值一致
+    pub async fn player_set_loop(&self, device_id: &str, loop_type: i32) -> Result<bool> {
+        debug!("MiNaService::player_set_loop");
+        self.ubus_request(
+            device_id,
+            "player_set_loop",
+            "mediaplayer",
+            json!({
+                "type": loop_type,
+                "media": "common",
+            }),
+        )
+        .await
+    }
+
+    /// 定时暂停播放，seconds 为 None 时取消定时
+    pub async fn player_set_shutdown_timer(
+        &self,
+        device_id: &str,
+        seconds: Option<u32>,
+    ) -> Result<bool> {
+        debug!("MiNaService::player_set_shutdown_timer");
+        let message = match seconds {
+            Some(seconds) => json!({
+                "action": "pause_later",
+                "second": seconds,
+                "media": "app_ios",
+            }),
+            None => json!({
+                "action": "cancel_ending",
+                "media": "app_ios",
+            }),
+        };
+        self.ubus_request(
+            device_id,
+            "player_set_shutdown_timer",
+            "mediaplayer",
+            message,
+        )
+        .await
+    }
+
+    /// 最近的对话记录，按时间倒序
+    pub async fn conversations(
+        &self,
+        device: &MiNADevice,
+        limit: usize,
+    ) -> Result<Vec<Conversation>> {
+        debug!("MiNaService::conversations");
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let uri = format!(
+            "/device_profile/v2/conversation?source=dialogu&hardware={}&timestamp={timestamp}&limit={limit}",
+            device.hardware
+        );
+        let mut relogin = true;
+        let resp: Response<serde_json::Value> = loop {
+            // 重新登录后 micoapi 的 serviceToken 已变化，每次请求前都重新复制
+            self.ensure_profile_cookies(device).await?;
+            match self
+                .request_host(USER_PROFILE_API, uri.clone(), None, Some(false))
+                .await
             {
-                // 记录调试信息
-                debug!(
-                    "Send to devno={} index={}: {}",
-                    devno,
-                    i,
-                    message
-                        .as_ref()
-                        .unwrap_or(&volume.map(|v| v.to_string()).unwrap_or_default())
-                );
-
-                let device_id = device["deviceID"].as_str().unwrap_or_default();
-
-                // 设置音量或将结果设为 true
-                result = if let Some(vol) = volume {
-                    self.player_set_volume(device_id, vol).await?
-                } else {
-                    true
-                };
+                Err(Error::AuthExpired(e)) if relogin => {
+                    warn!("Conversation auth expired: {e}, login again");
+                    relogin = false;
+                    self.account.lock().await.invalidate(MINA_SID).await?;
+                }
+                resp => break resp?,
+            }
+        };
 
-                // 如果前面成功且有消息，则发送语音
-                if result {
-                    if let Some(msg) = &message {
-                        result = self.text_to_speech(device_id, msg).await?;
-                    }
+        // data 为 JSON 字符串，没有对话时可能为空
+        let data = resp.data["data"].as_str().unwrap_or_default();
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        let list: Conversations =
+            serde_json::from_str(data).map_err(|e| Error::deserialize(data, e))?;
+        Ok(list.records)
+    }
+
+    /// 轮询对话记录，只返回开始监听之后的新对话，按时间顺序输出
+    ///
+    /// 请求失败时输出错误并在下一个周期继续轮询
+    pub fn watch_conversations<'a>(
+        &'a self,
+        device: &'a MiNADevice,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Conversation>> + 'a {
+        struct State {
+            last_time: Option<i64>,
+            pending: VecDeque<Conversation>,
+            polled: bool,
+        }
+        let state = State {
+            last_time: None,
+            pending: VecDeque::new(),
+            polled: false,
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(conversation) = state.pending.pop_front() {
+                    return Some((Ok(conversation), state));
+                }
+                if state.polled {
+                    tokio::time::sleep(interval).await;
                 }
+                state.polled = true;
 
-                // 发送失败时记录错误
-                if !result {
-                    error!(
-                        "Send failed: {}",
-                        message
-                            .as_ref()
-                            .unwrap_or(&volume.map(|v| v.to_string()).unwrap_or_default())
-                    );
+                let records = match self.conversations(device, WATCH_LIMIT).await {
+                    Ok(records) => records,
+                    Err(e) => return Some((Err(e), state)),
+                };
+                match state.last_time {
+                    // 首次轮询只记录位置，忽略历史对话
+                    None => state.last_time = Some(records.first().map_or(0, |r| r.time)),
+                    Some(last_time) => {
+                        let mut records: Vec<_> =
+                            records.into_iter().filter(|r| r.time > last_time).collect();
+                        records.sort_by_key(|r| r.time);
+                        if let Some(latest) = records.last() {
+                            state.last_time = Some(latest.time);
+                        }
+                        state.pending.extend(records);
+                    }
                 }
+            }
+        })
+    }
+
+    /// conversation 接口需要 deviceId cookie，以及 micoapi 的 serviceToken 与 userId
+    ///
+    /// 先确保 micoapi 已登录，否则没有可复制的 serviceToken
+    async fn ensure_profile_cookies(&self, device: &MiNADevice) -> Result<()> {
+        let mut account = self.account.lock().await;
+        account.get_sid(MINA_SID).await?;
+        copy_profile_cookies(&account.token.cookies, &device.id);
+        Ok(())
+    }
 
-                // 如果不是发送给所有设备(-1)或发送失败，则退出循环
-                if devno != -1 || !result {
-                    break;
+    /// 让小爱执行文字指令，如同对音箱说出，silent 时不播报回复
+    ///
+    /// 优先按音箱 MIoT 规格中的 execute-text-directive 动作执行，
+    /// 无 miot did、规格中没有该动作或动作执行失败时走 ubus nlp
+    pub async fn execute_command(
+        &self,
+        device: &MiNADevice,
+        text: &str,
+        silent: bool,
+    ) -> Result<bool> {
+        debug!("MiNaService::execute_command");
+        if !device.miot_did.is_empty()
+            && miot_executed(
+                &device.miot_did,
+                self.execute_by_miot(device, text, silent).await,
+            )
+        {
+            return Ok(true);
+        }
+
+        self.ubus_request(
+            &device.id,
+            "ai_service",
+            "mibrain",
+            json!({
+                "tts": if silent { 0 } else { 1 },
+                "nlp": 1,
+                "nlp_text": text,
+            }),
+        )
+        .await
+    }
+
+    /// 通过音箱规格中的 execute-text-directive 动作执行
+    async fn execute_by_miot(
+        &self,
+        device: &MiNADevice,
+        text: &str,
+        silent: bool,
+    ) -> Result<MiotResult> {
+        let miio = MiIOService::with_account(self.account.clone(), None);
+        let model = format!("xiaomi.wifispeaker.{}", device.hardware.to_lowercase());
+        let spec = miio.miot_spec_typed(&model).await?;
+        let (service, action) = spec
+            .action_by_name("execute-text-directive")
+            .ok_or_else(|| Error::Spec(format!("no execute-text-directive action in {model}")))?;
+        let mut args = vec![json!(text)];
+        if action.r#in.len() > 1 {
+            args.push(json!(silent));
+        }
+        miio.miot_action(&device.miot_did, (service.iid, action.iid), Some(args))
+            .await
+    }
+
+    /// 调整音量并/或播报消息，不同音箱并发执行，返回每个音箱的结果
+    ///
+    /// 选择全部音箱且带消息时，跳过不支持云端 TTS(`yunduantts`) 的音箱
+    pub async fn send_message(
+        &self,
+        devices: &[MiNADevice],
+        selector: &DeviceSelector,
+        message: Option<&str>,
+        volume: Option<i32>,
+    ) -> Result<Vec<SendReport>> {
+        debug!("MiNaService::send_message");
+        let selected: Vec<_> = devices
+            .iter()
+            .enumerate()
+            .filter(|(i, d)| selector.matches(i + 1, d))
+            .map(|(_, d)| d)
+            .collect();
+        if selected.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "no speaker matches {selector:?}"
+            )));
+        }
+
+        let tasks = selected.into_iter().map(|device| async move {
+            let outcome = if *selector == DeviceSelector::All
+                && message.is_some()
+                && device.capabilities.yunduantts == 0
+            {
+                SendOutcome::Skipped("cloud tts not supported".to_owned())
+            } else {
+                match self.send_to(device, message, volume).await {
+                    Ok(()) => SendOutcome::Sent,
+                    Err(e) => {
+                        error!("Send to {} failed: {e}", device.name);
+                        SendOutcome::Failed(e)
+                    }
                 }
+            };
+            SendReport {
+                device_id: device.id.clone(),
+                name: device.name.clone(),
+                outcome,
+            }
+        });
+        Ok(future::join_all(tasks).await)
+    }
+
+    async fn send_to(
+        &self,
+        device: &MiNADevice,
+        message: Option<&str>,
+        volume: Option<i32>,
+    ) -> Result<()> {
+        debug!("Send to {}: {message:?} volume: {volume:?}", device.name);
+        if let Some(volume) = volume {
+            if !self.player_set_volume(&device.id, volume).await? {
+                return Err(Error::Api {
+                    code: -1,
+                    message: "set volume failed".to_owned(),
+                });
             }
         }
+        if let Some(message) = message {
+            if !self.text_to_speech(&device.id, message).await? {
+                return Err(Error::Api {
+                    code: -1,
+                    message: "text to speech failed".to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 把 mina.mi.com 上的 serviceToken、userId 复制到 userprofile.mina.mi.com，并设置 deviceId
+fn copy_profile_cookies(store: &CookieStoreMutex, device_id: &str) {
+    let mut store = store.lock().unwrap();
+    let url = Url::parse(&format!("https://{USER_PROFILE_API}")).unwrap();
+    let mut cookies = store
+        .iter_any()
+        // STS 设置的 cookie 可能不带 Domain 属性，按 cookie store 记录的域名匹配
+        .filter(|c| {
+            c.domain
+                .as_cow()
+                .is_some_and(|d| d.ends_with("mina.mi.com") && d != USER_PROFILE_API)
+        })
+        .filter(|c| matches!(c.name(), "serviceToken" | "userId"))
+        .map(|c| cookie::Cookie::new(c.name().to_owned(), c.value().to_owned()))
+        .collect::<Vec<_>>();
+    cookies.push(cookie::Cookie::new("deviceId", device_id.to_owned()));
+    for mut cookie in cookies {
+        cookie.set_domain(USER_PROFILE_API);
+        cookie.set_path("/");
+        if let Err(e) = store.insert_raw(&cookie, &url) {
+            error!("Failed to insert cookie {cookie:?} to store: {:?}", e);
+        }
+    }
+}
+
+/// MIoT 动作是否执行成功，失败时由调用方回退到 ubus
+fn miot_executed(did: &str, result: Result<MiotResult>) -> bool {
+    match result {
+        Ok(Ok(_)) => true,
+        Ok(Err(status)) => {
+            warn!("Execute text directive on {did} failed: {status}, fall back to ubus");
+            false
+        }
+        Err(e) => {
+            warn!("Execute text directive on {did} failed: {e}, fall back to ubus");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_selector() {
+        let device: MiNADevice = serde_json::from_value(json!({
+            "address": "", "alias": "卧室", "brokerEndpoint": "", "brokerIndex": 0,
+            "current": false, "deviceID": "abc", "deviceProfile": "", "deviceSNProfile": "",
+            "hardware": "LX06", "mac": "", "miotDID": "1", "name": "小爱音箱Pro",
+            "presence": "online", "remoteCtrlType": "", "romVersion": "", "serialNumber": "",
+            "ssid": "", "capabilities": {}
+        }))
+        .unwrap_or_else(|e| panic!("{e}"));
+
+        assert_eq!(DeviceSelector::All, "-1".parse().unwrap());
+        assert_eq!(DeviceSelector::Index(2), "2".parse().unwrap());
+        assert!(DeviceSelector::Index(2).matches(2, &device));
+        assert!(!DeviceSelector::Index(1).matches(2, &device));
+        assert!("id:abc"
+            .parse::<DeviceSelector>()
+            .unwrap()
+            .matches(1, &device));
+        assert!("alias:卧室"
+            .parse::<DeviceSelector>()
+            .unwrap()
+            .matches(1, &device));
+        assert!("音箱"
+            .parse::<DeviceSelector>()
+            .unwrap()
+            .matches(1, &device));
+        assert!(!"电视"
+            .parse::<DeviceSelector>()
+            .unwrap()
+            .matches(1, &device));
+    }
 
-        Ok(result)
+    #[test]
+    fn execute_command_fallback() {
+        assert!(miot_executed("1", Ok(Ok(json!([])))));
+        // 动作返回错误码或请求失败时都回退到 ubus
+        assert!(!miot_executed(
+            "1",
+            Ok(Err(crate::MiotStatus::new(-704042011)))
+        ));
+        assert!(!miot_executed("1", Err(Error::Spec("no spec".to_owned()))));
+    }
+
+    #[test]
+    fn profile_cookies_after_login() {
+        // 没有 micoapi sid 的 token，只能设置 deviceId
+        let token = crate::store::Token::default();
+        copy_profile_cookies(&token.cookies, "abc");
+        let names = |token: &crate::store::Token| {
+            let mut cookies: Vec<_> = token
+                .cookie_infos()
+                .into_iter()
+                .filter(|c| c.domain == USER_PROFILE_API)
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect();
+            cookies.sort();
+            cookies
+        };
+        assert_eq!(names(&token), ["deviceId=abc"]);
+
+        // 登录 micoapi 后复制新的 serviceToken，重试时覆盖旧值
+        let url = Url::parse(&format!("https://{MINA_API}/sts")).unwrap();
+        for value in ["old", "new"] {
+            let mut store = token.cookies.lock().unwrap();
+            for raw in [
+                format!("serviceToken={value}; Path=/"),
+                "userId=1; Path=/".into(),
+            ] {
+                store
+                    .insert_raw(&cookie::Cookie::parse(raw).unwrap(), &url)
+                    .unwrap();
+            }
+            drop(store);
+            copy_profile_cookies(&token.cookies, "abc");
+        }
+        assert_eq!(
+            names(&token),
+            ["deviceId=abc", "serviceToken=new", "userId=1"]
+        );
     }
 }
@@ -0,0 +1,375 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    account::{CaptchaSolver, VerifyCodeProvider},
+    errors::{Error, Result},
+    store::{DirectoryBackend, FileBackend, TokenBackend, TokenKey},
+    Account, MiIOService, MiNaService, TokenStore,
+};
+
+/// 未指定 profile 且配置中没有 default 时使用的名称
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// 多账号配置文件，默认位于 `~/.config/mi-service/config.toml`
+///
+/// ```toml
+/// default = "home"
+/// # 可选，每个 profile 的 token 保存在 <token_dir>/<name>/token.json
+/// token_dir = "/run/secrets/mi-service"
+///
+/// [profile.home]
+/// user = "123456"
+/// password_env = "MI_PASS_HOME"
+/// token_file = "~/.mi.home.token"
+/// device = "客厅音箱"
+/// token_key_env = "MI_TOKEN_KEY_HOME"
+///
+/// [profile.office]
+/// user = "654321"
+/// password_file = "/run/secrets/mi_pass"
+/// region = "sg"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// 默认使用的 profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// 按 profile 分目录保存 token，profile 中的 token_file 优先
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_dir: Option<String>,
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// 单个账号的配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// 小米账号，只用 passToken 登录时可省略
+    #[serde(default)]
+    pub user: String,
+    /// 明文密码，建议改用 password_env 或 password_file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// 从环境变量读取密码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// 从文件读取密码，如容器中挂载的 secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+    /// miio 服务器区域，如 cn、de、sg，默认 cn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Token文件路径，默认 `~/.mi.token`，非 default profile 为 `~/.mi.<name>.token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<String>,
+    /// 默认设备，did 或名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// 从环境变量读取 token 加密密钥
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_key_env: Option<String>,
+    /// 从文件读取 token 加密密钥
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_key_file: Option<String>,
+}
+
+impl ProfileConfig {
+    /// 默认配置文件路径
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("mi-service").join("config.toml"))
+    }
+
+    /// 只包含一个 profile 的配置，用于命令行直接指定账号
+    pub fn single(name: &str, profile: Profile) -> Self {
+        Self {
+            default: Some(name.to_owned()),
+            token_dir: None,
+            profiles: BTreeMap::from([(name.to_owned(), profile)]),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// 读取配置文件
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        debug!("ProfileConfig::load {}", path.display());
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| Error::Config(format!("read {} failed: {e}", path.display())))?;
+        Self::from_toml(&content)
+    }
+
+    /// 读取默认配置文件，文件不存在时返回空配置
+    pub async fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path).await,
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// profile 的 token 存储后端: token_file、token_dir、默认文件依次生效
+    pub fn token_backend(&self, name: &str) -> Result<Arc<dyn TokenBackend>> {
+        let profile = self.profile(name)?;
+        Ok(match (&profile.token_file, &self.token_dir) {
+            (None, Some(dir)) => Arc::new(DirectoryBackend::new(expand_home(dir), name)),
+            _ => Arc::new(FileBackend::new(profile.token_path(name))),
+        })
+    }
+
+    /// 未指定名称时依次使用 default 配置、唯一的 profile、`default`
+    pub fn resolve_name<'a>(&'a self, name: Option<&'a str>) -> &'a str {
+        if let Some(name) = name.or(self.default.as_deref()) {
+            return name;
+        }
+        match self.profiles.keys().next() {
+            Some(name) if self.profiles.len() == 1 => name,
+            _ => DEFAULT_PROFILE,
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            Error::Config(format!(
+                "profile {name} not found, available: [{}]",
+                names.join(", ")
+            ))
+        })
+    }
+}
+
+impl Profile {
+    /// 按 password、password_env、password_file 的顺序获取密码，均未配置时返回 None
+    pub async fn password(&self) -> Result<Option<String>> {
+        if let Some(password) = &self.password {
+            return Ok(Some(password.clone()));
+        }
+        if let Some(key) = &self.password_env {
+            return std::env::var(key)
+                .map(Some)
+                .map_err(|_| Error::Config(format!("password env {key} is not set")));
+        }
+        if let Some(path) = &self.password_file {
+            let path = expand_home(&path.to_string_lossy());
+            let content = tokio::fs::read_to_string(&path).await.map_err(|e| {
+                Error::Config(format!("read password file {} failed: {e}", path.display()))
+            })?;
+            return Ok(Some(content.trim_end_matches(['\r', '\n']).to_owned()));
+        }
+        Ok(None)
+    }
+
+    /// token 加密密钥，未配置时取自 `MI_TOKEN_KEY` 或 `MI_TOKEN_KEY_FILE`
+    pub async fn token_key(&self) -> Result<Option<TokenKey>> {
+        if let Some(key) = &self.token_key_env {
+            let value = std::env::var(key)
+                .map_err(|_| Error::Config(format!("token key env {key} is not set")))?;
+            return TokenKey::parse(&value).map(Some);
+        }
+        if let Some(path) = &self.token_key_file {
+            return TokenKey::from_file(expand_home(path)).await.map(Some);
+        }
+        TokenKey::from_env().await
+    }
+
+    /// Token文件路径，`~` 展开为用户目录
+    pub fn token_path(&self, name: &str) -> PathBuf {
+        match &self.token_file {
+            Some(path) => expand_home(path),
+            None if name == DEFAULT_PROFILE => expand_home("~/.mi.token"),
+            None => expand_home(&format!("~/.mi.{name}.token")),
+        }
+    }
+}
+
+/// 展开路径开头的 `~` 或 `~/`，不支持 `~user` 形式
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    let rest = match path {
+        "~" => Some(""),
+        _ => path.strip_prefix("~/"),
+    };
+    match rest {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => PathBuf::from(path),
+        },
+        None => PathBuf::from(path),
+    }
+}
+
+/// 按 profile 创建 token 存储后端，用于接入数据库、密钥管理服务等
+pub type BackendFactory =
+    Arc<dyn Fn(&str, &Profile) -> Result<Arc<dyn TokenBackend>> + Send + Sync>;
+
+/// 按 profile 管理多个账号，每个 profile 只创建一个 [`Account`]
+///
+/// 同一 profile 的 miio 与 mina 服务共享账号和 token 文件。
+pub struct AccountManager {
+    config: ProfileConfig,
+    accounts: Mutex<HashMap<String, Arc<Mutex<Account>>>>,
+    backend_factory: Option<BackendFactory>,
+    verify_provider: Option<Arc<dyn VerifyCodeProvider>>,
+    captcha_solver: Option<Arc<dyn CaptchaSolver>>,
+}
+
+impl AccountManager {
+    pub fn new(config: ProfileConfig) -> Self {
+        Self {
+            config,
+            accounts: Mutex::new(HashMap::new()),
+            backend_factory: None,
+            verify_provider: None,
+            captcha_solver: None,
+        }
+    }
+
+    pub fn config(&self) -> &ProfileConfig {
+        &self.config
+    }
+
+    /// 替换默认的 token 存储后端
+    pub fn set_backend_factory(&mut self, factory: BackendFactory) {
+        self.backend_factory = Some(factory);
+    }
+
+    /// 设置新建账号使用的安全验证码提供者
+    pub fn set_verify_provider(&mut self, provider: Arc<dyn VerifyCodeProvider>) {
+        self.verify_provider = Some(provider);
+    }
+
+    /// 设置新建账号使用的图形验证码识别器
+    pub fn set_captcha_solver(&mut self, solver: Arc<dyn CaptchaSolver>) {
+        self.captcha_solver = Some(solver);
+    }
+
+    /// 获取 profile 对应的账号，首次调用时读取 token 并创建
+    ///
+    /// 读取密码与 token 时不持有锁，并发创建时保留先插入的账号。
+    pub async fn account(&self, name: Option<&str>) -> Result<Arc<Mutex<Account>>> {
+        let name = self.config.resolve_name(name);
+        debug!("AccountManager::account {name}");
+        if let Some(account) = self.accounts.lock().await.get(name) {
+            return Ok(account.clone());
+        }
+
+        let profile = self.config.profile(name)?;
+        let password = profile.password().await?;
+        let token_store = self.token_store(Some(name)).await?;
+        let mut account = Account::new(profile.user.clone(), password, token_store);
+        if let Some(provider) = &self.verify_provider {
+            account.set_verify_provider(provider.clone());
+        }
+        if let Some(solver) = &self.captcha_solver {
+            account.set_captcha_solver(solver.clone());
+        }
+
+        let mut accounts = self.accounts.lock().await;
+        let account = accounts
+            .entry(name.to_owned())
+            .or_insert_with(|| Arc::new(Mutex::new(account)));
+        Ok(account.clone())
+    }
+
+    /// 读取 profile 的 token，每次调用都重新从存储后端加载
+    pub async fn token_store(&self, name: Option<&str>) -> Result<TokenStore> {
+        let name = self.config.resolve_name(name);
+        let profile = self.config.profile(name)?;
+        let backend = match &self.backend_factory {
+            Some(factory) => factory(name, profile)?,
+            None => self.config.token_backend(name)?,
+        };
+        TokenStore::with_backend(backend, profile.token_key().await?).await
+    }
+
+    /// profile 的 miio 服务，使用配置中的区域
+    pub async fn miio(&self, name: Option<&str>) -> Result<MiIOService> {
+        let account = self.account(name).await?;
+        let profile = self.config.profile(self.config.resolve_name(name))?;
+        Ok(MiIOService::with_account(
+            account,
+            profile.region.as_deref(),
+        ))
+    }
+
+    /// profile 的 MiNA 小爱音箱服务
+    pub async fn mina(&self, name: Option<&str>) -> Result<MiNaService> {
+        Ok(MiNaService::with_account(self.account(name).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_config() {
+        let config = ProfileConfig::from_toml(
+            r#"
+            default = "home"
+
+            [profile.home]
+            user = "123"
+            password = "secret"
+            device = "客厅音箱"
+
+            [profile.office]
+            user = "456"
+            region = "sg"
+            token_file = "/tmp/office.token"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.resolve_name(None), "home");
+        assert_eq!(config.resolve_name(Some("office")), "office");
+        let office = config.profile("office").unwrap();
+        assert_eq!(office.region.as_deref(), Some("sg"));
+        assert_eq!(
+            office.token_path("office"),
+            PathBuf::from("/tmp/office.token")
+        );
+        assert_eq!(
+            config.profile("home").unwrap().device.as_deref(),
+            Some("客厅音箱")
+        );
+        assert!(matches!(config.profile("none"), Err(Error::Config(_))));
+        assert_eq!(
+            config.token_backend("office").unwrap().describe(),
+            "/tmp/office.token"
+        );
+
+        let single = ProfileConfig::single(
+            DEFAULT_PROFILE,
+            Profile {
+                user: "789".to_owned(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(single.resolve_name(None), DEFAULT_PROFILE);
+        assert!(single
+            .profile(DEFAULT_PROFILE)
+            .unwrap()
+            .token_path(DEFAULT_PROFILE)
+            .ends_with(".mi.token"));
+    }
+
+    #[test]
+    fn expand_home_prefix() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/.mi.token"), home.join(".mi.token"));
+        assert_eq!(expand_home("~alice/x"), PathBuf::from("~alice/x"));
+        assert_eq!(expand_home("/tmp/~/x"), PathBuf::from("/tmp/~/x"));
+    }
+}
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::errors::Error;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResultData<T> {
@@ -58,56 +61,430 @@ pub struct Extra {
     pub is_password_encrypt: i32,
 }
 
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct MiotSpecResp {
-//     pub instances: Vec<MiotSpec>,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct MiotSpec {
-//     pub status: String,
-//     pub model: String,
-//     pub version: i32,
-//     pub r#type: String,
-//     pub ts: i64,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct MiotSpecDetail {
-//     pub r#type: String,
-//     pub description: String,
-//     pub services: Vec<MiotSpecService>,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct MiotSpecService {
-//     pub iid: i32,
-//     pub r#type: String,
-//     pub description: String,
-//     pub properties: Vec<MiotSpecProperty>,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct MiotSpecProperty {
-//     pub iid: i32,
-//     pub r#type: String,
-//     pub description: String,
-//     pub format: String,
-//     pub access: Vec<String>,
-//     pub unit: String,
-//     #[serde(rename = "value-range", skip_serializing_if = "Option::is_none")]
-//     pub value_range: Option<Vec<i32>>,
-//     #[serde(rename = "value-list", skip_serializing_if = "Option::is_none")]
-//     pub value_list: Option<Vec<ValueList>>,
-//     // pub min: i32,
-//     // pub max: i32,
-//     // pub step: i32,
-//     // pub permission: Vec<String>,
-//     // pub additional: String,
-// }
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct ValueList {
-//     pub value: i32,
-//     pub description: String,
-// }
+/// miot-spec.org `instances` 接口返回的型号列表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecInstances {
+    pub instances: Vec<MiotSpecInstance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecInstance {
+    pub status: String,
+    pub model: String,
+    pub version: i32,
+    pub r#type: String,
+    pub ts: i64,
+}
+
+/// miot-spec.org `instance?type=` 接口返回的设备规格
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecDetail {
+    pub r#type: String,
+    pub description: String,
+    #[serde(default)]
+    pub services: Vec<MiotSpecService>,
+}
+
+impl MiotSpecDetail {
+    pub fn service(&self, siid: i32) -> Option<&MiotSpecService> {
+        self.services.iter().find(|s| s.iid == siid)
+    }
+
+    pub fn property(&self, siid: i32, piid: i32) -> Option<&MiotSpecProperty> {
+        self.service(siid)?
+            .properties
+            .iter()
+            .find(|p| p.iid == piid)
+    }
+
+    pub fn action(&self, siid: i32, aiid: i32) -> Option<&MiotSpecAction> {
+        self.service(siid)?.actions.iter().find(|a| a.iid == aiid)
+    }
+
+    pub fn event(&self, siid: i32, eiid: i32) -> Option<&MiotSpecEvent> {
+        self.service(siid)?.events.iter().find(|e| e.iid == eiid)
+    }
+
+    /// 按 urn 中的名称查找动作，如 `execute-text-directive`
+    pub fn action_by_name(&self, name: &str) -> Option<(&MiotSpecService, &MiotSpecAction)> {
+        self.services.iter().find_map(|s| {
+            s.actions
+                .iter()
+                .find(|a| a.r#type.split(':').nth(3) == Some(name))
+                .map(|a| (s, a))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecService {
+    pub iid: i32,
+    pub r#type: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<MiotSpecProperty>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<MiotSpecAction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<MiotSpecEvent>,
+}
+
+impl MiotSpecService {
+    pub fn property(&self, piid: i32) -> Option<&MiotSpecProperty> {
+        self.properties.iter().find(|p| p.iid == piid)
+    }
+
+    pub fn action(&self, aiid: i32) -> Option<&MiotSpecAction> {
+        self.actions.iter().find(|a| a.iid == aiid)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecProperty {
+    pub iid: i32,
+    pub r#type: String,
+    pub description: String,
+    pub format: MiotFormat,
+    #[serde(default)]
+    pub access: Vec<MiotAccess>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(
+        rename = "value-range",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub value_range: Option<ValueRange>,
+    #[serde(
+        rename = "value-list",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub value_list: Option<Vec<ValueList>>,
+}
+
+impl MiotSpecProperty {
+    pub fn readable(&self) -> bool {
+        self.access.contains(&MiotAccess::Read)
+    }
+
+    pub fn writable(&self) -> bool {
+        self.access.contains(&MiotAccess::Write)
+    }
+
+    pub fn notifiable(&self) -> bool {
+        self.access.contains(&MiotAccess::Notify)
+    }
+
+    /// 单位，规格中的 "none" 视为无单位
+    pub fn unit(&self) -> Option<&str> {
+        self.unit.as_deref().filter(|u| *u != "none")
+    }
+
+    /// 将文本转换为属性值，支持 value-list 中的描述名称，并校验取值范围
+    pub fn parse_value(&self, text: &str) -> Result<Value, Error> {
+        if let Some(item) = self
+            .value_list
+            .iter()
+            .flatten()
+            .find(|it| it.description.eq_ignore_ascii_case(text))
+        {
+            return Ok(Value::from(item.value));
+        }
+
+        let value = self.format.parse_value(text)?;
+        if let (Some(range), Some(v)) = (&self.value_range, value.as_f64()) {
+            if !range.contains(v) {
+                return Err(Error::InvalidArgument(format!(
+                    "{} value {text} out of range [{}, {}]",
+                    self.description, range.min, range.max
+                )));
+            }
+            if !range.fits_step(v) {
+                return Err(Error::InvalidArgument(format!(
+                    "{} value {text} is not a multiple of step {} from {}",
+                    self.description, range.step, range.min
+                )));
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecAction {
+    pub iid: i32,
+    pub r#type: String,
+    pub description: String,
+    /// 输入参数对应的 piid 列表
+    #[serde(default)]
+    pub r#in: Vec<i32>,
+    /// 输出参数对应的 piid 列表
+    #[serde(default)]
+    pub out: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecEvent {
+    pub iid: i32,
+    pub r#type: String,
+    pub description: String,
+    /// 事件参数对应的 piid 列表
+    #[serde(default)]
+    pub arguments: Vec<i32>,
+}
+
+/// 属性的数据格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MiotFormat {
+    Bool,
+    Uint8,
+    Uint16,
+    Uint32,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float,
+    String,
+    Hex,
+    #[serde(other)]
+    Unknown,
+}
+
+impl MiotFormat {
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Self::Uint8
+                | Self::Uint16
+                | Self::Uint32
+                | Self::Int8
+                | Self::Int16
+                | Self::Int32
+                | Self::Int64
+        )
+    }
+
+    /// 整数格式的取值范围
+    pub fn int_range(&self) -> Option<(i64, i64)> {
+        match self {
+            Self::Uint8 => Some((0, u8::MAX.into())),
+            Self::Uint16 => Some((0, u16::MAX.into())),
+            Self::Uint32 => Some((0, u32::MAX.into())),
+            Self::Int8 => Some((i8::MIN.into(), i8::MAX.into())),
+            Self::Int16 => Some((i16::MIN.into(), i16::MAX.into())),
+            Self::Int32 => Some((i32::MIN.into(), i32::MAX.into())),
+            Self::Int64 => Some((i64::MIN, i64::MAX)),
+            _ => None,
+        }
+    }
+
+    /// 按格式将文本转换为属性值，整数按格式宽度校验范围
+    pub fn parse_value(&self, text: &str) -> Result<Value, Error> {
+        let invalid = || Error::InvalidArgument(format!("invalid {self:?} value: {text}"));
+        match self {
+            Self::Bool => match text.to_ascii_lowercase().as_str() {
+                "true" | "1" | "on" | "yes" => Ok(Value::Bool(true)),
+                "false" | "0" | "off" | "no" => Ok(Value::Bool(false)),
+                _ => Err(invalid()),
+            },
+            Self::Float => text.parse::<f64>().map(Value::from).map_err(|_| invalid()),
+            Self::String | Self::Hex | Self::Unknown => Ok(Value::String(text.to_owned())),
+            _ => {
+                let (min, max) = self.int_range().unwrap_or((i64::MIN, i64::MAX));
+                text.parse::<i64>()
+                    .ok()
+                    .filter(|v| (min..=max).contains(v))
+                    .map(Value::from)
+                    .ok_or_else(invalid)
+            }
+        }
+    }
+}
+
+/// 属性的访问权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MiotAccess {
+    Read,
+    Write,
+    Notify,
+    #[serde(other)]
+    Unknown,
+}
+
+/// 取值范围，规格中以 `[min, max, step]` 数组表示
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueRange {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl ValueRange {
+    pub fn contains(&self, value: f64) -> bool {
+        value >= self.min && value <= self.max
+    }
+
+    /// 从 min 起按 step 递增可取到该值，step 不大于0时不限制
+    pub fn fits_step(&self, value: f64) -> bool {
+        if self.step <= 0.0 {
+            return true;
+        }
+        let steps = (value - self.min) / self.step;
+        (steps - steps.round()).abs() < 1e-6
+    }
+}
+
+impl Serialize for ValueRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(3))?;
+        for v in [self.min, self.max, self.step] {
+            // 整数值按整数输出，与原始规格保持一致
+            if v.fract() == 0.0 && v.abs() < i64::MAX as f64 {
+                seq.serialize_element(&(v as i64))?;
+            } else {
+                seq.serialize_element(&v)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ValueRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = Vec::<f64>::deserialize(deserializer)?;
+        match v.as_slice() {
+            [min, max] => Ok(Self {
+                min: *min,
+                max: *max,
+                step: 1.0,
+            }),
+            [min, max, step, ..] => Ok(Self {
+                min: *min,
+                max: *max,
+                step: *step,
+            }),
+            _ => Err(de::Error::invalid_length(v.len(), &"[min, max, step]")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueList {
+    pub value: i64,
+    pub description: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_spec_detail() {
+        let spec = r#"{
+            "type": "urn:miot-spec-v2:device:light:0000A001:yeelink-color1:1",
+            "description": "Light",
+            "services": [{
+                "iid": 2,
+                "type": "urn:miot-spec-v2:service:light:00007802:yeelink-color1:1",
+                "description": "Light",
+                "properties": [
+                    {"iid": 1, "type": "urn:miot-spec-v2:property:on:00000006:yeelink-color1:1",
+                     "description": "Switch Status", "format": "bool",
+                     "access": ["read", "write", "notify"]},
+                    {"iid": 2, "type": "urn:miot-spec-v2:property:mode:00000008:yeelink-color1:1",
+                     "description": "Mode", "format": "uint8", "access": ["read", "write"],
+                     "value-list": [{"value": 0, "description": "Day"}, {"value": 1, "description": "Night"}]},
+                    {"iid": 3, "type": "urn:miot-spec-v2:property:brightness:0000000D:yeelink-color1:1",
+                     "description": "Brightness", "format": "uint8", "access": ["read", "write", "notify"],
+                     "unit": "percentage", "value-range": [1, 100, 1]}
+                ],
+                "actions": [
+                    {"iid": 1, "type": "urn:miot-spec-v2:action:toggle:00002811:yeelink-color1:1",
+                     "description": "Toggle", "in": [], "out": []}
+                ]
+            }]
+        }"#;
+        let detail: MiotSpecDetail = serde_json::from_str(spec).unwrap();
+        let on = detail.property(2, 1).unwrap();
+        assert_eq!(MiotFormat::Bool, on.format);
+        assert!(on.readable() && on.writable() && on.notifiable());
+
+        let mode = detail.property(2, 2).unwrap();
+        assert_eq!(2, mode.value_list.as_ref().unwrap().len());
+        assert!(!mode.notifiable());
+
+        let brightness = detail.property(2, 3).unwrap();
+        assert_eq!(Some("percentage"), brightness.unit());
+        assert_eq!(
+            Some(ValueRange {
+                min: 1.0,
+                max: 100.0,
+                step: 1.0
+            }),
+            brightness.value_range
+        );
+        assert_eq!(
+            r#"[1,100,1]"#,
+            serde_json::to_string(&brightness.value_range).unwrap()
+        );
+
+        assert_eq!(Value::Bool(true), on.parse_value("on").unwrap());
+        assert_eq!(Value::from(1), mode.parse_value("night").unwrap());
+        assert_eq!(Value::from(50), brightness.parse_value("50").unwrap());
+        assert!(brightness.parse_value("101").is_err());
+        assert!(brightness.parse_value("bright").is_err());
+        assert!(brightness.parse_value("-1").is_err());
+
+        assert_eq!("Toggle", detail.action(2, 1).unwrap().description);
+        let (service, action) = detail.action_by_name("toggle").unwrap();
+        assert_eq!((2, 1), (service.iid, action.iid));
+        assert!(detail.action_by_name("on").is_none());
+        assert!(detail.event(2, 1).is_none());
+    }
+
+    #[test]
+    fn parse_value_by_format() {
+        assert_eq!(
+            Value::from(255),
+            MiotFormat::Uint8.parse_value("255").unwrap()
+        );
+        assert!(MiotFormat::Uint8.parse_value("256").is_err());
+        assert!(MiotFormat::Uint16.parse_value("-1").is_err());
+        assert!(MiotFormat::Int8.parse_value("-129").is_err());
+        assert_eq!(
+            Value::from(-32768),
+            MiotFormat::Int16.parse_value("-32768").unwrap()
+        );
+        assert!(MiotFormat::Int32.parse_value("2147483648").is_err());
+
+        let color_temperature: MiotSpecProperty = serde_json::from_value(serde_json::json!({
+            "iid": 3, "type": "urn:miot-spec-v2:property:color-temperature:0000000F:yeelink-color1:1",
+            "description": "Color Temperature", "format": "uint32", "access": ["read", "write"],
+            "value-range": [1700, 6500, 100]
+        }))
+        .unwrap();
+        assert_eq!(
+            Value::from(2700),
+            color_temperature.parse_value("2700").unwrap()
+        );
+        assert!(color_temperature.parse_value("2750").is_err());
+
+        let temperature = ValueRange {
+            min: 16.0,
+            max: 30.0,
+            step: 0.5,
+        };
+        assert!(temperature.fits_step(26.5));
+        assert!(!temperature.fits_step(26.2));
+    }
+}
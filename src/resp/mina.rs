@@ -36,7 +36,9 @@ pub struct MiNADevice {
     pub capabilities: Capabilities,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 音箱能力，缺失的字段视为不支持
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Capabilities {
     #[serde(rename = "ai_instruction")]
     pub ai_instruction: i32,
@@ -104,3 +106,158 @@ pub struct Capabilities {
     pub yueyu: i32,
     pub yunduantts: i32,
 }
+
+/// 播放状态，对应 `status` 字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "i32", into = "i32")]
+pub enum PlayState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+    Unknown(i32),
+}
+
+impl From<i32> for PlayState {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Stopped,
+            1 => Self::Playing,
+            2 => Self::Paused,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<PlayState> for i32 {
+    fn from(value: PlayState) -> Self {
+        match value {
+            PlayState::Stopped => 0,
+            PlayState::Playing => 1,
+            PlayState::Paused => 2,
+            PlayState::Unknown(v) => v,
+        }
+    }
+}
+
+/// 播放器状态，`player_get_play_status` 返回的 info 字段
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    #[serde(default)]
+    pub status: PlayState,
+    #[serde(default)]
+    pub volume: i32,
+    #[serde(default)]
+    pub loop_type: i32,
+    #[serde(default)]
+    pub media_type: i32,
+    /// 当前曲目，未播放时为空
+    #[serde(
+        rename = "play_song_detail",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub track: Option<PlayerTrack>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerTrack {
+    #[serde(default)]
+    pub audio_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// 播放进度(毫秒)
+    #[serde(default)]
+    pub position: i64,
+    /// 总时长(毫秒)
+    #[serde(default)]
+    pub duration: i64,
+}
+
+/// 小爱音箱对话记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+    /// 用户说的话
+    #[serde(default)]
+    pub query: String,
+    /// 毫秒时间戳
+    pub time: i64,
+    #[serde(default)]
+    pub answers: Vec<ConversationAnswer>,
+}
+
+impl Conversation {
+    /// 小爱回答的 TTS 文本，多条回答以换行连接
+    pub fn answer_tts(&self) -> Option<String> {
+        let texts: Vec<&str> = self
+            .answers
+            .iter()
+            .filter_map(|a| a.tts.as_ref())
+            .map(|t| t.text.as_str())
+            .collect();
+        if texts.is_empty() {
+            None
+        } else {
+            Some(texts.join("\n"))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationAnswer {
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tts: Option<AnswerTts>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerTts {
+    pub text: String,
+}
+
+/// conversation 接口 data 字段(JSON 字符串)的内容
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Conversations {
+    #[serde(default)]
+    pub records: Vec<Conversation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_player_status() {
+        let info = r#"{"status":1,"volume":30,"loop_type":1,"media_type":3,
+            "play_song_detail":{"audio_id":"123","position":1000,"duration":200000,"title":"Song"}}"#;
+        let status: PlayerStatus = serde_json::from_str(info).unwrap();
+        assert_eq!(PlayState::Playing, status.status);
+        assert_eq!(30, status.volume);
+        let track = status.track.unwrap();
+        assert_eq!(Some("Song"), track.title.as_deref());
+        assert_eq!(200000, track.duration);
+
+        let idle: PlayerStatus = serde_json::from_str(r#"{"status":2,"volume":10}"#).unwrap();
+        assert_eq!(PlayState::Paused, idle.status);
+        assert!(idle.track.is_none());
+    }
+
+    #[test]
+    fn deserialize_conversations() {
+        let data = r#"{"bitSet":[0,1],"records":[{"bitSet":[0,1],"requestId":"abc","time":1700000000000,
+            "query":"今天天气","answers":[{"bitSet":[0],"type":"TTS","tts":{"bitSet":[0],"text":"晴"}}]},
+            {"requestId":"def","time":1699999990000,"query":"暂停","answers":[]}],"nextEndTime":1699999990000}"#;
+        let list: Conversations = serde_json::from_str(data).unwrap();
+        assert_eq!(2, list.records.len());
+        assert_eq!("今天天气", list.records[0].query);
+        assert_eq!(Some("晴".to_owned()), list.records[0].answer_tts());
+        assert_eq!(None, list.records[1].answer_tts());
+    }
+}
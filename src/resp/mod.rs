@@ -1,7 +1,15 @@
 use serde::{Deserialize, Serialize};
 
-pub use miio::{MiIODevice, MiIODevices, ResultData};
-pub use mina::MiNaDevices;
+pub use miio::{
+    MiIODevice, MiIODevices, MiotAccess, MiotFormat, MiotSpecAction, MiotSpecDetail, MiotSpecEvent,
+    MiotSpecInstance, MiotSpecInstances, MiotSpecProperty, MiotSpecService, ResultData, ValueList,
+    ValueRange,
+};
+pub(crate) use mina::Conversations;
+pub use mina::{
+    AnswerTts, Capabilities, Conversation, ConversationAnswer, MiNADevice, MiNaDevices, PlayState,
+    PlayerStatus, PlayerTrack,
+};
 
 mod miio;
 mod mina;
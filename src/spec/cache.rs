@@ -0,0 +1,423 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, warn};
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{
+    errors::{Error, Result},
+    resp::MiotSpecInstances,
+};
+
+/// 缓存文件格式版本，结构变化时递增，旧版本文件视为不存在
+const CACHE_VERSION: u32 = 1;
+/// 默认缓存有效期: 7天
+const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+const INSTANCES_FILE: &str = "instances.json";
+const TYPES_DIR: &str = "types";
+const SPEC_SERVER: &str = "http://miot-spec.org/miot-spec-v2";
+
+/// MIoT 规格本地缓存
+///
+/// 型号列表存放于 `<dir>/instances.json`，规格详情按类型存放于 `<dir>/types/`。
+/// 默认目录为 `$MIOT_SPEC_PATH`，未设置时使用系统缓存目录(如 `~/.cache/mi-service/miot-spec`)。
+#[derive(Debug, Clone)]
+pub struct SpecCache {
+    pub dir: PathBuf,
+    /// 超过有效期的缓存会在下次读取时尝试条件刷新
+    pub ttl: Duration,
+    /// 离线模式下只读取缓存，不访问网络
+    pub offline: bool,
+    client: reqwest::Client,
+}
+
+/// 缓存文件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry<T> {
+    version: u32,
+    /// 获取(或确认未变化)的时间，unix 秒
+    fetched_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    data: T,
+}
+
+impl<T> CacheEntry<T> {
+    fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+}
+
+/// 缓存状态，用于 `spec cache status`
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecCacheStatus {
+    pub dir: PathBuf,
+    pub ttl: u64,
+    pub offline: bool,
+    pub instances: Option<SpecCacheItem>,
+    pub types: Vec<SpecCacheItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecCacheItem {
+    pub name: String,
+    pub fetched_at: u64,
+    pub age: u64,
+    pub stale: bool,
+}
+
+enum Fetched<T> {
+    Modified(CacheEntry<T>),
+    NotModified,
+}
+
+impl Default for SpecCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpecCache {
+    /// 根据环境变量创建缓存
+    ///
+    /// - `MIOT_SPEC_PATH`: 缓存目录
+    /// - `MIOT_SPEC_TTL`: 有效期，单位秒
+    /// - `MIOT_SPEC_OFFLINE`: 为 `1`/`true` 时启用离线模式
+    pub fn new() -> Self {
+        let dir = env::var("MIOT_SPEC_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| default_dir());
+        let ttl = env::var("MIOT_SPEC_TTL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TTL);
+        let offline = env::var("MIOT_SPEC_OFFLINE")
+            .map(|s| s == "1" || s.eq_ignore_ascii_case("true"))
+            .unwrap_or_default();
+
+        Self::with_dir(dir, ttl, offline)
+    }
+
+    pub fn with_dir(dir: impl Into<PathBuf>, ttl: Duration, offline: bool) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            offline,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// 型号与规格类型的映射，key: model, value: type
+    pub async fn instances(&self) -> Result<BTreeMap<String, String>> {
+        let path = self.dir.join(INSTANCES_FILE);
+        let url = format!("{SPEC_SERVER}/instances?status=all");
+        self.load(&path, &url, INSTANCES_FILE, |v: MiotSpecInstances| {
+            v.instances
+                .into_iter()
+                .map(|it| (it.model, it.r#type))
+                .collect()
+        })
+        .await
+    }
+
+    /// 指定类型的原始规格详情
+    pub async fn spec(&self, spec_type: &str) -> Result<Value> {
+        let path = self.type_path(spec_type);
+        let url = format!("{SPEC_SERVER}/instance?type={spec_type}");
+        self.load(&path, &url, spec_type, |v: Value| v).await
+    }
+
+    /// 刷新型号列表及已缓存的规格详情，force 为 true 时忽略有效期
+    pub async fn refresh(&self, force: bool) -> Result<()> {
+        if self.offline {
+            return Err(Error::Spec("miot spec cache is in offline mode".to_owned()));
+        }
+        let cache = Self {
+            ttl: if force { Duration::ZERO } else { self.ttl },
+            ..self.clone()
+        };
+        cache.instances().await?;
+        for spec_type in self.cached_types().await? {
+            cache.spec(&spec_type).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn status(&self) -> Result<SpecCacheStatus> {
+        let instances = read_entry::<Value>(&self.dir.join(INSTANCES_FILE))
+            .await
+            .map(|entry| self.item(INSTANCES_FILE.to_owned(), &entry));
+
+        let mut types = Vec::new();
+        for spec_type in self.cached_types().await? {
+            if let Some(entry) = read_entry::<Value>(&self.type_path(&spec_type)).await {
+                types.push(self.item(spec_type, &entry));
+            }
+        }
+
+        Ok(SpecCacheStatus {
+            dir: self.dir.clone(),
+            ttl: self.ttl.as_secs(),
+            offline: self.offline,
+            instances,
+            types,
+        })
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        let instances = self.dir.join(INSTANCES_FILE);
+        if instances.exists() {
+            fs::remove_file(&instances).await?;
+        }
+        let types = self.dir.join(TYPES_DIR);
+        if types.exists() {
+            fs::remove_dir_all(&types).await?;
+        }
+        Ok(())
+    }
+
+    fn item(&self, name: String, entry: &CacheEntry<Value>) -> SpecCacheItem {
+        let age = entry.age();
+        SpecCacheItem {
+            name,
+            fetched_at: entry.fetched_at,
+            age: age.as_secs(),
+            stale: age > self.ttl,
+        }
+    }
+
+    fn type_path(&self, spec_type: &str) -> PathBuf {
+        let name: String = spec_type
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(TYPES_DIR).join(format!("{name}.json"))
+    }
+
+    /// 已缓存的规格类型，从缓存文件中读取原始类型名
+    async fn cached_types(&self) -> Result<Vec<String>> {
+        let dir = self.dir.join(TYPES_DIR);
+        let mut types = Vec::new();
+        if !dir.exists() {
+            return Ok(types);
+        }
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(cached) = read_entry::<Value>(&entry.path()).await {
+                if let Some(spec_type) = cached.data["type"].as_str() {
+                    types.push(spec_type.to_owned());
+                }
+            }
+        }
+        types.sort();
+        Ok(types)
+    }
+
+    /// 读取缓存，缺失或过期时从网络获取
+    async fn load<T, R, F>(&self, path: &Path, url: &str, name: &str, convert: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        R: DeserializeOwned,
+        F: FnOnce(R) -> T,
+    {
+        let cached = read_entry::<T>(path).await;
+        match cached {
+            Some(entry) if self.offline || entry.age() <= self.ttl => {
+                debug!("SpecCache::load hit {name}");
+                return Ok(entry.data);
+            }
+            None if self.offline => {
+                return Err(Error::Spec(format!(
+                    "miot spec {name} is not cached in {} and offline mode is enabled",
+                    self.dir.display()
+                )));
+            }
+            _ => {}
+        }
+
+        debug!("SpecCache::load fetch {url}");
+        match self.fetch(url, cached.as_ref(), convert).await {
+            Ok(Fetched::Modified(entry)) => {
+                write_entry(path, &entry).await?;
+                Ok(entry.data)
+            }
+            Ok(Fetched::NotModified) => {
+                let mut entry = cached.expect("not modified without cache");
+                entry.fetched_at = now();
+                write_entry(path, &entry).await?;
+                Ok(entry.data)
+            }
+            Err(e) => match cached {
+                Some(entry) => {
+                    warn!("Refresh miot spec {name} failed, use stale cache: {e}");
+                    Ok(entry.data)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn fetch<T, R, F>(
+        &self,
+        url: &str,
+        cached: Option<&CacheEntry<T>>,
+        convert: F,
+    ) -> Result<Fetched<T>>
+    where
+        R: DeserializeOwned,
+        F: FnOnce(R) -> T,
+    {
+        let mut builder = self.client.get(url);
+        if let Some(entry) = cached {
+            if let Some(etag) = &entry.etag {
+                builder = builder.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = builder.send().await.map_err(|e| Error::transport(url, e))?;
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED && cached.is_some() {
+            return Ok(Fetched::NotModified);
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let text = response
+            .text()
+            .await
+            .map_err(|e| Error::transport(url, e))?;
+        if !status.is_success() {
+            return Err(Error::Http {
+                url: url.to_owned(),
+                status,
+                body: text,
+            });
+        }
+        let data = convert(
+            serde_json::from_str::<R>(&text).map_err(|e| Error::deserialize(text.clone(), e))?,
+        );
+
+        Ok(Fetched::Modified(CacheEntry {
+            version: CACHE_VERSION,
+            fetched_at: now(),
+            etag,
+            last_modified,
+            data,
+        }))
+    }
+}
+
+fn default_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("mi-service")
+        .join("miot-spec")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn read_entry<T: DeserializeOwned>(path: &Path) -> Option<CacheEntry<T>> {
+    let content = fs::read_to_string(path).await.ok()?;
+    match serde_json::from_str::<CacheEntry<T>>(&content) {
+        Ok(entry) if entry.version == CACHE_VERSION => Some(entry),
+        Ok(entry) => {
+            debug!(
+                "Ignore miot spec cache {} with version {}",
+                path.display(),
+                entry.version
+            );
+            None
+        }
+        Err(e) => {
+            warn!("Ignore broken miot spec cache {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// 先写临时文件再重命名，避免中断时留下不完整的缓存
+async fn write_entry<T: Serialize>(path: &Path, entry: &CacheEntry<T>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    let content = serde_json::to_vec(entry).map_err(|e| Error::Spec(e.to_string()))?;
+    fs::write(&tmp, content).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SPEC_TYPE: &str = "urn:miot-spec-v2:device:light:0000A001:yeelink-color1:1";
+
+    #[tokio::test]
+    async fn offline_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SpecCache::with_dir(dir.path(), DEFAULT_TTL, true);
+
+        assert!(cache.spec(SPEC_TYPE).await.is_err());
+
+        let entry = CacheEntry {
+            version: CACHE_VERSION,
+            fetched_at: 0,
+            etag: None,
+            last_modified: None,
+            data: json!({"type": SPEC_TYPE, "description": "Light", "services": []}),
+        };
+        write_entry(&cache.type_path(SPEC_TYPE), &entry)
+            .await
+            .unwrap();
+
+        // 离线模式下过期缓存仍然可用
+        let spec = cache.spec(SPEC_TYPE).await.unwrap();
+        assert_eq!("Light", spec["description"]);
+
+        let status = cache.status().await.unwrap();
+        assert!(status.instances.is_none());
+        assert_eq!(1, status.types.len());
+        assert_eq!(SPEC_TYPE, status.types[0].name);
+        assert!(status.types[0].stale);
+
+        cache.clear().await.unwrap();
+        assert!(cache.spec(SPEC_TYPE).await.is_err());
+        assert!(cache.refresh(false).await.is_err());
+    }
+}
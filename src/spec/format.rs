@@ -0,0 +1,253 @@
+use std::{fmt::Write, str::FromStr};
+
+use crate::{
+    errors::{Error, Result},
+    resp::{MiotAccess, MiotSpecDetail, MiotSpecProperty},
+};
+
+/// 规格详情的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecFormat {
+    /// 可读的 siid/piid/aiid 表格
+    Text,
+    /// Python 常量文件，同 Yonsm/MiService 的 `--format python`
+    Python,
+    /// 紧凑 JSON
+    Json,
+    Yaml,
+}
+
+impl FromStr for SpecFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "text" | "table" => Ok(Self::Text),
+            "python" | "py" => Ok(Self::Python),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => Err(Error::InvalidArgument(format!(
+                "unknown spec format {s}, expect one of text, python, json, yaml"
+            ))),
+        }
+    }
+}
+
+impl SpecFormat {
+    pub fn render(&self, spec: &MiotSpecDetail) -> Result<String> {
+        match self {
+            Self::Text => Ok(render_text(spec)),
+            Self::Python => Ok(render_python(spec)),
+            Self::Json => serde_json::to_string(spec).map_err(|e| Error::Spec(e.to_string())),
+            Self::Yaml => serde_yaml::to_string(spec).map_err(|e| Error::Spec(e.to_string())),
+        }
+    }
+}
+
+/// 取 urn 中的名称段，如 `urn:miot-spec-v2:property:on:00000006:...` 中的 `on`
+fn urn_name(urn: &str) -> &str {
+    urn.split(':').nth(3).unwrap_or(urn)
+}
+
+fn access_flags(access: &[MiotAccess]) -> String {
+    [
+        (MiotAccess::Read, 'r'),
+        (MiotAccess::Write, 'w'),
+        (MiotAccess::Notify, 'n'),
+    ]
+    .iter()
+    .map(|(a, c)| if access.contains(a) { *c } else { '-' })
+    .collect()
+}
+
+fn property_range(prop: &MiotSpecProperty) -> String {
+    let mut range = String::new();
+    if let Some(r) = &prop.value_range {
+        range = format!("[{}, {}, {}]", r.min, r.max, r.step);
+    }
+    if let Some(unit) = prop.unit() {
+        if !range.is_empty() {
+            range.push(' ');
+        }
+        range.push_str(unit);
+    }
+    range
+}
+
+fn render_text(spec: &MiotSpecDetail) -> String {
+    let mut text = format!("{}\n{}\n", spec.description, spec.r#type);
+    for svc in &spec.services {
+        let _ = writeln!(
+            text,
+            "\n[{}] {} ({})",
+            svc.iid,
+            svc.description,
+            urn_name(&svc.r#type)
+        );
+        for prop in &svc.properties {
+            let _ = writeln!(
+                text,
+                "  {:<8}{:<28}{:<8}{:<5}{:<30}{}",
+                format!("{}.{}", svc.iid, prop.iid),
+                urn_name(&prop.r#type),
+                format!("{:?}", prop.format).to_lowercase(),
+                access_flags(&prop.access),
+                prop.description,
+                property_range(prop),
+            );
+            for item in prop.value_list.iter().flatten() {
+                let _ = writeln!(text, "  {:<8}  {} = {}", "", item.value, item.description);
+            }
+        }
+        for action in &svc.actions {
+            let _ = writeln!(
+                text,
+                "  {:<8}{:<28}{:<13}{:<30}in: {:?} out: {:?}",
+                format!("{}-{}", svc.iid, action.iid),
+                urn_name(&action.r#type),
+                "action",
+                action.description,
+                action.r#in,
+                action.out,
+            );
+        }
+        for event in &svc.events {
+            let _ = writeln!(
+                text,
+                "  {:<8}{:<28}{:<13}{:<30}arguments: {:?}",
+                format!("{}~{}", svc.iid, event.iid),
+                urn_name(&event.r#type),
+                "event",
+                event.description,
+                event.arguments,
+            );
+        }
+    }
+    // 去除对齐填充产生的行尾空格
+    text.lines()
+        .map(|l| format!("{}\n", l.trim_end()))
+        .collect()
+}
+
+/// 转换为 Python 常量名，如 `color-temperature` => `COLOR_TEMPERATURE`
+fn const_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+fn render_python(spec: &MiotSpecDetail) -> String {
+    let mut text = format!(
+        "# Generated by https://github.com/shenshouer/mi-service\n# http://miot-spec.org/miot-spec-v2/instance?type={}\n",
+        spec.r#type
+    );
+    for svc in &spec.services {
+        let svc_name = const_name(urn_name(&svc.r#type));
+        let _ = writeln!(
+            text,
+            "\n################ {} ################",
+            svc.description
+        );
+        let _ = writeln!(text, "{svc_name} = {}", svc.iid);
+        for prop in &svc.properties {
+            let name = format!("{svc_name}_{}", const_name(urn_name(&prop.r#type)));
+            let range = property_range(prop);
+            let _ = writeln!(
+                text,
+                "{name} = {}, {}  # {} {} {}{}",
+                svc.iid,
+                prop.iid,
+                prop.description,
+                format!("{:?}", prop.format).to_lowercase(),
+                access_flags(&prop.access),
+                if range.is_empty() {
+                    range
+                } else {
+                    format!(" {range}")
+                },
+            );
+            for item in prop.value_list.iter().flatten() {
+                let value = if item.description.is_empty() {
+                    item.value.to_string()
+                } else {
+                    const_name(&item.description)
+                };
+                let _ = writeln!(text, "{name}_{value} = {}", item.value);
+            }
+        }
+        for action in &svc.actions {
+            let _ = writeln!(
+                text,
+                "{svc_name}_ACTION_{} = {}, {}  # {} in: {:?} out: {:?}",
+                const_name(urn_name(&action.r#type)),
+                svc.iid,
+                action.iid,
+                action.description,
+                action.r#in,
+                action.out,
+            );
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"{
+        "type": "urn:miot-spec-v2:device:light:0000A001:yeelink-color1:1",
+        "description": "Light",
+        "services": [{
+            "iid": 2,
+            "type": "urn:miot-spec-v2:service:light:00007802:yeelink-color1:1",
+            "description": "Light",
+            "properties": [
+                {"iid": 1, "type": "urn:miot-spec-v2:property:on:00000006:yeelink-color1:1",
+                 "description": "Switch Status", "format": "bool", "access": ["read", "write", "notify"]},
+                {"iid": 2, "type": "urn:miot-spec-v2:property:mode:00000008:yeelink-color1:1",
+                 "description": "Mode", "format": "uint8", "access": ["read", "write"],
+                 "value-list": [{"value": 0, "description": "Day"}, {"value": 1, "description": "Night Light"}]},
+                {"iid": 3, "type": "urn:miot-spec-v2:property:color-temperature:0000000F:yeelink-color1:1",
+                 "description": "Color Temperature", "format": "uint32", "access": ["read"],
+                 "unit": "kelvin", "value-range": [1700, 6500, 1]}
+            ],
+            "actions": [
+                {"iid": 1, "type": "urn:miot-spec-v2:action:toggle:00002811:yeelink-color1:1",
+                 "description": "Toggle", "in": [], "out": []}
+            ]
+        }]
+    }"#;
+
+    #[test]
+    fn render_python_constants() {
+        let spec: MiotSpecDetail = serde_json::from_str(SPEC).unwrap();
+        let text = SpecFormat::Python.render(&spec).unwrap();
+        assert!(text.contains("\nLIGHT = 2\n"));
+        assert!(text.contains("\nLIGHT_ON = 2, 1  # Switch Status bool rwn\n"));
+        assert!(text.contains("\nLIGHT_MODE_NIGHT_LIGHT = 1\n"));
+        assert!(text.contains(
+            "\nLIGHT_COLOR_TEMPERATURE = 2, 3  # Color Temperature uint32 r-- [1700, 6500, 1] kelvin\n"
+        ));
+        assert!(text.contains("\nLIGHT_ACTION_TOGGLE = 2, 1  # Toggle in: [] out: []\n"));
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!(SpecFormat::Text, "table".parse().unwrap());
+        assert_eq!(SpecFormat::Yaml, "YML".parse().unwrap());
+        assert!("xml".parse::<SpecFormat>().is_err());
+    }
+}
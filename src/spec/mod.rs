@@ -0,0 +1,5 @@
+pub use cache::{SpecCache, SpecCacheItem, SpecCacheStatus};
+pub use format::SpecFormat;
+
+mod cache;
+mod format;
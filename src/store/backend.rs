@@ -0,0 +1,266 @@
+use std::{
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use log::debug;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    errors::{Error, Result},
+    utils::get_random,
+};
+
+pub type TokenFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Token 存储后端，保存序列化后的 token 内容
+///
+/// 除内置的文件、内存、目录后端外，可以实现该trait将 token 存入数据库或密钥管理服务，
+/// 再通过 [`TokenStore::with_backend`](super::TokenStore::with_backend) 使用。
+pub trait TokenBackend: Send + Sync {
+    /// 读取 token，不存在时返回 None
+    fn load(&self) -> TokenFuture<'_, Option<Vec<u8>>>;
+
+    /// 保存 token，覆盖已有内容
+    fn save<'a>(&'a self, content: &'a [u8]) -> TokenFuture<'a, ()>;
+
+    /// 删除 token，不存在时忽略
+    fn remove(&self) -> TokenFuture<'_, ()>;
+
+    /// 用于日志和错误信息的描述，如文件路径
+    fn describe(&self) -> String;
+}
+
+/// 单个文件存储，先写临时文件再原子重命名，权限为 0600
+#[derive(Debug, Clone)]
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn write(&self, content: &[u8]) -> std::io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        fs::create_dir_all(&dir).await?;
+
+        let name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tmp = dir.join(format!(".{name}.{}.tmp", get_random(8)));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let result = async {
+            let mut file = options.open(&tmp).await?;
+            file.write_all(content).await?;
+            file.sync_all().await?;
+            fs::rename(&tmp, &self.path).await
+        }
+        .await;
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp).await;
+            return result;
+        }
+
+        // 同步目录，确保重命名落盘
+        #[cfg(unix)]
+        if let Ok(dir) = fs::File::open(&dir).await {
+            let _ = dir.sync_all().await;
+        }
+        Ok(())
+    }
+}
+
+impl TokenBackend for FileBackend {
+    fn load(&self) -> TokenFuture<'_, Option<Vec<u8>>> {
+        Box::pin(async move {
+            match fs::read(&self.path).await {
+                Ok(content) => Ok(Some(content)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(Error::TokenStore(format!(
+                    "read {} failed: {e}",
+                    self.path.display()
+                ))),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, content: &'a [u8]) -> TokenFuture<'a, ()> {
+        Box::pin(async move {
+            debug!("FileBackend::save {}", self.path.display());
+            self.write(content).await.map_err(|e| {
+                Error::TokenStore(format!("write {} failed: {e}", self.path.display()))
+            })
+        })
+    }
+
+    fn remove(&self) -> TokenFuture<'_, ()> {
+        Box::pin(async move {
+            match fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(Error::TokenStore(format!(
+                    "remove {} failed: {e}",
+                    self.path.display()
+                ))),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// 内存存储，进程退出后丢失，克隆的实例共享同一份内容
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    content: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用已有的 token 内容，如从环境变量或挂载的 secret 中读取
+    pub fn with_content(content: impl Into<Vec<u8>>) -> Self {
+        Self {
+            content: Arc::new(Mutex::new(Some(content.into()))),
+        }
+    }
+
+    /// 当前保存的内容
+    pub fn content(&self) -> Option<Vec<u8>> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Vec<u8>>> {
+        self.content.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl TokenBackend for MemoryBackend {
+    fn load(&self) -> TokenFuture<'_, Option<Vec<u8>>> {
+        let content = self.content();
+        Box::pin(async move { Ok(content) })
+    }
+
+    fn save<'a>(&'a self, content: &'a [u8]) -> TokenFuture<'a, ()> {
+        *self.lock() = Some(content.to_vec());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove(&self) -> TokenFuture<'_, ()> {
+        *self.lock() = None;
+        Box::pin(async { Ok(()) })
+    }
+
+    fn describe(&self) -> String {
+        "memory".to_owned()
+    }
+}
+
+/// 按 profile 分目录存储，token 位于 `<dir>/<profile>/token.json`
+///
+/// 适合在容器中挂载同一个目录保存多个账号。
+#[derive(Debug, Clone)]
+pub struct DirectoryBackend {
+    dir: PathBuf,
+    file: FileBackend,
+}
+
+impl DirectoryBackend {
+    pub fn new(dir: impl Into<PathBuf>, profile: &str) -> Self {
+        let dir = dir.into().join(profile);
+        let file = FileBackend::new(dir.join("token.json"));
+        Self { dir, file }
+    }
+
+    /// profile 对应的目录
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl TokenBackend for DirectoryBackend {
+    fn load(&self) -> TokenFuture<'_, Option<Vec<u8>>> {
+        self.file.load()
+    }
+
+    fn save<'a>(&'a self, content: &'a [u8]) -> TokenFuture<'a, ()> {
+        Box::pin(async move {
+            let mut builder = fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            builder.mode(0o700);
+            builder.create(&self.dir).await.map_err(|e| {
+                Error::TokenStore(format!("create {} failed: {e}", self.dir.display()))
+            })?;
+            self.file.save(content).await
+        })
+    }
+
+    fn remove(&self) -> TokenFuture<'_, ()> {
+        self.file.remove()
+    }
+
+    fn describe(&self) -> String {
+        self.file.describe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = DirectoryBackend::new(dir.path(), "home");
+        assert!(backend.load().await.unwrap().is_none());
+
+        backend.save(b"first").await.unwrap();
+        backend.save(b"second").await.unwrap();
+        assert_eq!(
+            backend.load().await.unwrap().as_deref(),
+            Some(&b"second"[..])
+        );
+
+        let path = dir.path().join("home").join("token.json");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // 只剩目标文件，没有残留的临时文件
+        assert_eq!(std::fs::read_dir(backend.dir()).unwrap().count(), 1);
+
+        backend.remove().await.unwrap();
+        backend.remove().await.unwrap();
+        assert!(!path.exists());
+
+        let memory = MemoryBackend::new();
+        memory.save(b"token").await.unwrap();
+        assert_eq!(memory.clone().content().as_deref(), Some(&b"token"[..]));
+        memory.remove().await.unwrap();
+        assert!(memory.load().await.unwrap().is_none());
+    }
+}
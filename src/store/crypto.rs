@@ -0,0 +1,282 @@
+use std::{env, fmt, path::Path};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+
+/// 加密文件的格式标识
+const FORMAT: &str = "mi-service-token";
+const VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+/// 口令环境变量
+pub const TOKEN_KEY_ENV: &str = "MI_TOKEN_KEY";
+/// 密钥文件路径环境变量
+pub const TOKEN_KEY_FILE_ENV: &str = "MI_TOKEN_KEY_FILE";
+
+/// Token 加密密钥
+///
+/// 64位十六进制或密钥文件中32字节的二进制内容直接作为密钥，其他内容视为口令，使用 Argon2id 派生密钥。
+#[derive(Clone)]
+pub enum TokenKey {
+    Passphrase(String),
+    Raw([u8; 32]),
+}
+
+impl fmt::Debug for TokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("TokenKey::Passphrase(***)"),
+            Self::Raw(_) => f.write_str("TokenKey::Raw(***)"),
+        }
+    }
+}
+
+impl TokenKey {
+    /// 64位十六进制视为原始密钥，其他文本视为口令，只去除末尾的换行符
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text.is_empty() {
+            return Err(Error::TokenStore("token key is empty".to_owned()));
+        }
+        if let Some(key) = hex::decode(text)
+            .ok()
+            .and_then(|k| <[u8; 32]>::try_from(k).ok())
+        {
+            return Ok(Self::Raw(key));
+        }
+        Ok(Self::Passphrase(text.to_owned()))
+    }
+
+    /// 从密钥文件读取，32字节的二进制内容直接作为密钥，文本内容同 [`TokenKey::parse`]
+    pub async fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = tokio::fs::read(path).await.map_err(|e| {
+            Error::TokenStore(format!("read token key {} failed: {e}", path.display()))
+        })?;
+        Self::from_bytes(&content).ok_or_else(|| {
+            Error::TokenStore(format!(
+                "token key {} must be 32 bytes or utf-8 text",
+                path.display()
+            ))
+        })?
+    }
+
+    fn from_bytes(content: &[u8]) -> Option<Result<Self>> {
+        match std::str::from_utf8(content) {
+            // 可打印的文本即使恰好32字节也视为口令
+            Ok(text)
+                if content.len() != 32
+                    || !text
+                        .trim_end_matches(['\r', '\n'])
+                        .chars()
+                        .any(char::is_control) =>
+            {
+                Some(Self::parse(text))
+            }
+            _ => <[u8; 32]>::try_from(content)
+                .ok()
+                .map(|key| Ok(Self::Raw(key))),
+        }
+    }
+
+    /// 依次读取环境变量 `MI_TOKEN_KEY`、`MI_TOKEN_KEY_FILE`，均未设置时返回 None
+    pub async fn from_env() -> Result<Option<Self>> {
+        if let Ok(key) = env::var(TOKEN_KEY_ENV) {
+            return Self::parse(&key).map(Some);
+        }
+        match env::var(TOKEN_KEY_FILE_ENV) {
+            Ok(path) => Self::from_file(path).await.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kdf {
+    Argon2id,
+    Raw,
+}
+
+/// 加密后的文件内容
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    version: u32,
+    kdf: Kdf,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Envelope {
+    fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(content)
+            .ok()
+            .filter(|e| e.format == FORMAT)
+    }
+}
+
+/// 派生后的密钥，保存时复用，避免每次保存都重新派生
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    kdf: Kdf,
+    salt: Vec<u8>,
+    key: [u8; 32],
+}
+
+impl Cipher {
+    /// 使用新的随机盐
+    pub(crate) fn new(key: &TokenKey) -> Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(key, salt)
+    }
+
+    fn derive(key: &TokenKey, salt: Vec<u8>) -> Result<Self> {
+        match key {
+            TokenKey::Raw(key) => Ok(Self {
+                kdf: Kdf::Raw,
+                salt: Vec::new(),
+                key: *key,
+            }),
+            TokenKey::Passphrase(passphrase) => {
+                let mut derived = [0u8; 32];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), &salt, &mut derived)
+                    .map_err(|e| Error::TokenStore(format!("derive token key failed: {e}")))?;
+                Ok(Self {
+                    kdf: Kdf::Argon2id,
+                    salt,
+                    key: derived,
+                })
+            }
+        }
+    }
+
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Error::TokenStore("encrypt token failed".to_owned()))?;
+        let envelope = Envelope {
+            format: FORMAT.to_owned(),
+            version: VERSION,
+            kdf: self.kdf,
+            salt: STANDARD.encode(&self.salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        serde_json::to_vec_pretty(&envelope)
+            .map_err(|e| Error::TokenStore(format!("serialize encrypted token failed: {e}")))
+    }
+}
+
+/// 是否为加密的 token 文件
+pub(crate) fn is_encrypted(content: &[u8]) -> bool {
+    Envelope::parse(content).is_some()
+}
+
+/// 解密 token，同时返回派生的密钥用于之后保存
+pub(crate) fn open(key: &TokenKey, content: &[u8]) -> Result<(Vec<u8>, Cipher)> {
+    let invalid = |what: &str| Error::TokenStore(format!("invalid encrypted token: {what}"));
+    let envelope = Envelope::parse(content).ok_or_else(|| invalid("format"))?;
+    if envelope.version != VERSION {
+        return Err(invalid(&format!("version {}", envelope.version)));
+    }
+    let salt = STANDARD
+        .decode(&envelope.salt)
+        .map_err(|_| invalid("salt"))?;
+    let nonce = STANDARD
+        .decode(&envelope.nonce)
+        .ok()
+        .filter(|n| n.len() == 24)
+        .ok_or_else(|| invalid("nonce"))?;
+    let ciphertext = STANDARD
+        .decode(&envelope.ciphertext)
+        .map_err(|_| invalid("ciphertext"))?;
+
+    let cipher = match (envelope.kdf, key) {
+        (Kdf::Raw, TokenKey::Raw(_)) | (Kdf::Argon2id, TokenKey::Passphrase(_)) => {
+            Cipher::derive(key, salt)?
+        }
+        (Kdf::Raw, _) => {
+            return Err(Error::TokenStore(
+                "token is encrypted with a raw key, but a passphrase was given".to_owned(),
+            ))
+        }
+        (Kdf::Argon2id, _) => {
+            return Err(Error::TokenStore(
+                "token is encrypted with a passphrase, but a raw key was given".to_owned(),
+            ))
+        }
+    };
+    let plaintext = XChaCha20Poly1305::new(&cipher.key.into())
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| {
+            Error::TokenStore("decrypt token failed, wrong key or corrupted".to_owned())
+        })?;
+    Ok((plaintext, cipher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let key = TokenKey::parse("correct horse").unwrap();
+        let sealed = Cipher::new(&key)
+            .unwrap()
+            .seal(b"{\"user_id\":\"1\"}")
+            .unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!is_encrypted(b"{\"user_id\":\"1\"}"));
+
+        let (plaintext, cipher) = open(&key, &sealed).unwrap();
+        assert_eq!(plaintext, b"{\"user_id\":\"1\"}");
+        // 复用派生的密钥再次保存，仍可用口令解密
+        let resealed = cipher.seal(b"again").unwrap();
+        assert_eq!(open(&key, &resealed).unwrap().0, b"again");
+
+        let wrong = TokenKey::parse("wrong horse").unwrap();
+        assert!(matches!(open(&wrong, &sealed), Err(Error::TokenStore(_))));
+
+        let raw = TokenKey::from_bytes(&[7u8; 32]).unwrap().unwrap();
+        assert!(matches!(raw, TokenKey::Raw(_)));
+        let hex_key = TokenKey::parse(&"07".repeat(32)).unwrap();
+        let sealed = Cipher::new(&raw).unwrap().seal(b"raw").unwrap();
+        assert_eq!(open(&hex_key, &sealed).unwrap().0, b"raw");
+        assert!(open(&key, &sealed).is_err());
+    }
+
+    #[test]
+    fn passphrase_of_32_chars() {
+        let text = "abcdefghijklmnopqrstuvwxyz012345";
+        assert_eq!(text.len(), 32);
+        assert!(matches!(TokenKey::parse(text), Ok(TokenKey::Passphrase(p)) if p == text));
+        assert!(matches!(
+            TokenKey::from_bytes(text.as_bytes()),
+            Some(Ok(TokenKey::Passphrase(_)))
+        ));
+        // 首尾空格属于口令
+        assert!(
+            matches!(TokenKey::parse(" pass \n"), Ok(TokenKey::Passphrase(p)) if p == " pass ")
+        );
+        let line = format!("{}\n", &text[..31]);
+        assert!(matches!(
+            TokenKey::from_bytes(line.as_bytes()),
+            Some(Ok(TokenKey::Passphrase(_)))
+        ));
+        assert!(TokenKey::from_bytes(&[0xff; 31]).is_none());
+    }
+}
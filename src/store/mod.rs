@@ -1,19 +1,29 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use log::{debug, error};
+use cookie_store::CookieExpiration;
+use log::debug;
 use reqwest_cookie_store::CookieStoreMutex;
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncWriteExt;
 use url::Url;
 
-use crate::utils::get_random;
+use crate::{
+    errors::{Error, Result},
+    utils::get_random,
+};
 
+pub use backend::{DirectoryBackend, FileBackend, MemoryBackend, TokenBackend, TokenFuture};
+pub use crypto::{TokenKey, TOKEN_KEY_ENV, TOKEN_KEY_FILE_ENV};
+
+mod backend;
+mod crypto;
 mod serde_cookies;
 
 // Token存储结构体
 #[derive(Clone)]
 pub struct TokenStore {
-    pub path: String,
+    backend: Arc<dyn TokenBackend>,
+    /// 设置密钥时加密保存
+    cipher: Option<crypto::Cipher>,
     pub token: Token,
 }
 
@@ -24,31 +34,89 @@ pub struct Token {
     pub pass_token: String,
     // pub sid: HashMap<String, (String, String)>, // key: sid, value (ssecurity, service_token)
     pub sid: HashMap<String, String>, // key: sid, value (ssecurity, service_token)
+    /// 各 sid 的 serviceToken 过期时间，unix 秒
+    #[serde(default)]
+    pub sid_expires: HashMap<String, u64>,
     #[serde(with = "serde_cookies")]
     pub cookies: Arc<CookieStoreMutex>,
 }
 
+/// 保存的单个 cookie
+#[derive(Debug, Clone, Serialize)]
+pub struct CookieInfo {
+    pub domain: String,
+    pub name: String,
+    pub value: String,
+    /// 过期时间，unix 秒，None 为会话 cookie
+    pub expires_at: Option<i64>,
+}
+
 impl Token {
+    /// 保存的 cookie 列表，包括已过期的
+    pub fn cookie_infos(&self) -> Vec<CookieInfo> {
+        let store = self.cookies.lock().unwrap();
+        store
+            .iter_any()
+            .map(|cookie| CookieInfo {
+                domain: cookie.domain.as_cow().unwrap_or_default().into_owned(),
+                name: cookie.name().to_owned(),
+                value: cookie.value().to_owned(),
+                expires_at: match &cookie.expires {
+                    CookieExpiration::AtUtc(at) => Some(at.unix_timestamp()),
+                    CookieExpiration::SessionEnd => None,
+                },
+            })
+            .collect()
+    }
+
     pub fn clean(&mut self) {
         self.user_id = String::new();
         self.pass_token = String::new();
         self.sid = HashMap::new();
+        self.sid_expires = HashMap::new();
         let mut store = self.cookies.lock().unwrap();
         store.clear();
     }
 }
 
 impl TokenStore {
-    pub async fn new(path: String) -> Self {
-        let mut token = if Path::new(&path).exists() {
-            match tokio::fs::read_to_string(&path).await {
-                Ok(content) => serde_json::from_str::<Token>(&content).unwrap(),
-                Err(e) => {
-                    panic!("Load token from {path} failed: {e}");
-                }
+    /// 使用文件存储，文件不存在时创建新的 token
+    ///
+    /// 密钥取自 `MI_TOKEN_KEY` 或 `MI_TOKEN_KEY_FILE`，设置后 token 加密保存，
+    /// 未加密的旧文件仍可读取，下次保存时转为加密格式。
+    pub async fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let key = TokenKey::from_env().await?;
+        Self::with_backend(Arc::new(FileBackend::new(path)), key).await
+    }
+
+    /// 使用自定义存储后端，key 为 None 时以明文保存
+    pub async fn with_backend(
+        backend: Arc<dyn TokenBackend>,
+        key: Option<TokenKey>,
+    ) -> Result<Self> {
+        debug!("TokenStore::with_backend {}", backend.describe());
+        let content = backend.load().await?;
+        let (content, cipher) = match (content, &key) {
+            (Some(content), Some(key)) if crypto::is_encrypted(&content) => {
+                let (plaintext, cipher) = crypto::open(key, &content)?;
+                (Some(plaintext), Some(cipher))
+            }
+            (Some(content), None) if crypto::is_encrypted(&content) => {
+                return Err(Error::TokenStore(format!(
+                    "token {} is encrypted, set {TOKEN_KEY_ENV} or {TOKEN_KEY_FILE_ENV}",
+                    backend.describe()
+                )));
             }
-        } else {
-            Token::default()
+            (content, key) => (content, key.as_ref().map(crypto::Cipher::new).transpose()?),
+        };
+        let mut token = match content {
+            Some(content) => serde_json::from_slice::<Token>(&content).map_err(|e| {
+                Error::TokenStore(format!(
+                    "parse token from {} failed: {e}",
+                    backend.describe()
+                ))
+            })?,
+            None => Token::default(),
         };
 
         if token.device_id.is_empty() {
@@ -63,7 +131,7 @@ impl TokenStore {
                     .domain("account.xiaomi.com")
                     .path("/")
                     .build();
-                store.insert_raw(&cookie, &request_url).unwrap();
+                let _ = store.insert_raw(&cookie, &request_url);
             }
 
             if !store.contains("account.xiaomi.com", "/", "deviceId") {
@@ -71,31 +139,77 @@ impl TokenStore {
                     .domain("account.xiaomi.com")
                     .path("/")
                     .build();
-                store.insert_raw(&cookie, &request_url).unwrap();
+                let _ = store.insert_raw(&cookie, &request_url);
             }
         }
 
-        Self { path, token }
+        Ok(Self {
+            backend,
+            cipher,
+            token,
+        })
     }
 
-    pub async fn save(&self) {
-        debug!("Token::save");
-        if let Ok(content) = serde_json::to_string_pretty(&self.token) {
-            let mut file = tokio::fs::File::create(&self.path)
-                .await
-                .expect("Open token file failed");
+    pub fn backend(&self) -> &Arc<dyn TokenBackend> {
+        &self.backend
+    }
 
-            if let Err(er) = file.write(content.as_bytes()).await {
-                error!("Save token to {} failed: {er}", self.path);
-            }
-        }
+    /// 是否加密保存
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// 更换密钥，None 表示以明文保存，调用 [`save`](Self::save) 后生效
+    pub fn set_key(&mut self, key: Option<&TokenKey>) -> Result<()> {
+        self.cipher = key.map(crypto::Cipher::new).transpose()?;
+        Ok(())
     }
 
-    pub async fn clean(&mut self) {
-        if Path::new(&self.path).exists() {
-            let _ = tokio::fs::remove_file(&self.path).await;
+    pub async fn save(&self) -> Result<()> {
+        debug!("Token::save");
+        let content = serde_json::to_vec_pretty(&self.token)
+            .map_err(|e| Error::TokenStore(format!("serialize token failed: {e}")))?;
+        match &self.cipher {
+            Some(cipher) => self.backend.save(&cipher.seal(&content)?).await,
+            None => self.backend.save(&content).await,
         }
+    }
 
+    pub async fn clean(&mut self) -> Result<()> {
         self.token.clean();
+        self.backend.remove().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn encrypted_token() {
+        let backend = MemoryBackend::with_content(
+            r#"{"device_id":"D","user_id":"1","pass_token":"p","sid":{},"cookies":[]}"#,
+        );
+        let key = TokenKey::Raw([1; 32]);
+
+        // 明文 token 可直接读取，设置密钥后加密保存
+        let store = TokenStore::with_backend(Arc::new(backend.clone()), Some(key.clone()))
+            .await
+            .unwrap();
+        assert_eq!(store.token.pass_token, "p");
+        store.save().await.unwrap();
+        let content = backend.content().unwrap();
+        assert!(crypto::is_encrypted(&content));
+
+        let err = TokenStore::with_backend(Arc::new(backend.clone()), None).await;
+        assert!(matches!(err, Err(Error::TokenStore(_))));
+
+        let mut store = TokenStore::with_backend(Arc::new(backend.clone()), Some(key))
+            .await
+            .unwrap();
+        assert_eq!(store.token.user_id, "1");
+        store.set_key(None).unwrap();
+        store.save().await.unwrap();
+        assert!(!crypto::is_encrypted(&backend.content().unwrap()));
     }
 }